@@ -0,0 +1,196 @@
+use hub_core::{anyhow::Result, chrono::Utc, prelude::*, uuid::Uuid};
+use sea_orm::{
+    prelude::*, ActiveValue::Set, Condition, DbBackend, Order, QueryOrder, QuerySelect, Statement,
+};
+use serde_json::{json, Map, Value};
+
+use crate::{
+    entities::{credential_audit_log, prelude::*, sea_orm_active_enums::CredentialAuditAction},
+    graphql::objects::{Credential, CredentialStatus},
+};
+
+/// The number of audit entries returned when a page size is not requested.
+const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// The largest number of audit entries returned at once.
+const MAX_PAGE_SIZE: usize = 100;
+
+/// Reads the ID of the newest audit entry, or 0 when the log is empty.
+const WATERMARK: &str = "SELECT COALESCE(MAX(id), 0) AS id FROM credential_audit_log";
+
+/// Who changed a credential and where the request came from. Changes made by the service itself,
+/// such as expiring a credential, have no user.
+#[derive(Debug, Clone, Default)]
+pub struct Actor {
+    pub user_id: Option<Uuid>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+/// Appends an entry to the audit log of `credential`.
+///
+/// # Errors
+/// Returns an error if the database write fails.
+pub async fn record<C: ConnectionTrait>(
+    conn: &C,
+    actor: &Actor,
+    action: CredentialAuditAction,
+    credential: &Credential,
+    diff: Value,
+) -> Result<()> {
+    let active_model = credential_audit_log::ActiveModel {
+        organization_id: Set(credential.organization_id),
+        client_id: Set(credential.client_id.clone()),
+        action: Set(action),
+        actor_id: Set(actor.user_id),
+        ip: Set(actor.ip.clone()),
+        user_agent: Set(actor.user_agent.clone()),
+        diff: Set(diff),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+
+    CredentialAuditLog::insert(active_model)
+        .exec_without_returning(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// The ID of the newest entry of the audit log. Every change to a credential appends an entry, so
+/// a credential whose log has no entry past the watermark has not changed since it was read.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn watermark<C: ConnectionTrait>(conn: &C) -> Result<i64> {
+    let row = conn
+        .query_one(Statement::from_string(
+            DbBackend::Postgres,
+            WATERMARK.to_string(),
+        ))
+        .await?
+        .ok_or_else(|| anyhow!("no audit log watermark returned"))?;
+
+    Ok(row.try_get("", "id")?)
+}
+
+/// Whether an entry was appended to the audit log of `client_id` after `watermark`. Issuing an
+/// access token changes nothing about the credential, so those entries are not counted.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn changed_since<C: ConnectionTrait>(
+    conn: &C,
+    client_id: &str,
+    watermark: i64,
+) -> Result<bool> {
+    let entry = CredentialAuditLog::find()
+        .filter(credential_audit_log::Column::ClientId.eq(client_id))
+        .filter(credential_audit_log::Column::Id.gt(watermark))
+        .filter(credential_audit_log::Column::Action.ne(CredentialAuditAction::TokenIssued))
+        .limit(1)
+        .one(conn)
+        .await?;
+
+    Ok(entry.is_some())
+}
+
+/// Describes the fields that changed between two snapshots of a credential as an object mapping
+/// each field to its `old` and `new` value. A missing snapshot means the credential did not exist
+/// at that point, so every field of the other one is included.
+#[must_use]
+pub fn diff(before: Option<&Credential>, after: Option<&Credential>) -> Value {
+    let before = before.map(snapshot).unwrap_or_default();
+    let after = after.map(snapshot).unwrap_or_default();
+
+    let changes = after
+        .keys()
+        .chain(before.keys())
+        .filter(|field| before.get(*field) != after.get(*field))
+        .map(|field| {
+            (
+                field.clone(),
+                json!({
+                    "old": before.get(field).cloned().unwrap_or_default(),
+                    "new": after.get(field).cloned().unwrap_or_default(),
+                }),
+            )
+        })
+        .collect::<Map<_, _>>();
+
+    Value::Object(changes)
+}
+
+fn snapshot(credential: &Credential) -> Map<String, Value> {
+    let status = match credential.status {
+        CredentialStatus::Active => "active",
+        CredentialStatus::Suspended => "suspended",
+    };
+
+    let snapshot = json!({
+        "name": credential.name,
+        "description": credential.description,
+        "scopes": credential.scopes.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        "status": status,
+        "token_lifetime": credential.token_lifetime,
+        "expires_at": credential.expires_at.map(|at| at.to_string()),
+    });
+
+    match snapshot {
+        Value::Object(snapshot) => snapshot,
+        _ => Map::new(),
+    }
+}
+
+/// A page of audit entries, newest first, with whether entries precede and follow it.
+pub type Page = (Vec<credential_audit_log::Model>, bool, bool);
+
+/// Reads a page of the audit entries matching `condition`, newest first. `after` and `before` are
+/// entry IDs bounding the page; `first` and `last` take entries from its start or end.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn page<C: ConnectionTrait>(
+    conn: &C,
+    condition: Condition,
+    after: Option<i64>,
+    before: Option<i64>,
+    first: Option<usize>,
+    last: Option<usize>,
+) -> Result<Page> {
+    let mut query = CredentialAuditLog::find().filter(condition);
+
+    if let Some(after) = after {
+        query = query.filter(credential_audit_log::Column::Id.lt(after));
+    }
+
+    if let Some(before) = before {
+        query = query.filter(credential_audit_log::Column::Id.gt(before));
+    }
+
+    let (limit, from_end) = match (first, last) {
+        (Some(first), _) => (first, false),
+        (None, Some(last)) => (last, true),
+        (None, None) => (DEFAULT_PAGE_SIZE, false),
+    };
+    let limit = limit.min(MAX_PAGE_SIZE);
+
+    let order = if from_end { Order::Asc } else { Order::Desc };
+
+    let mut entries = query
+        .order_by(credential_audit_log::Column::Id, order)
+        .limit(u64::try_from(limit + 1)?)
+        .all(conn)
+        .await?;
+
+    let has_more = entries.len() > limit;
+    entries.truncate(limit);
+
+    if from_end {
+        entries.reverse();
+
+        Ok((entries, has_more, before.is_some()))
+    } else {
+        Ok((entries, after.is_some(), has_more))
+    }
+}
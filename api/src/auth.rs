@@ -0,0 +1,108 @@
+use hub_core::clap;
+use poem::{async_trait, http::StatusCode, Endpoint, Error, Middleware, Request, Result};
+
+/// The header internal callers send their shared secret in.
+pub const API_KEY_HEADER: &str = "X-API-KEY";
+
+/// Arguments for authenticating the internal callers of this service
+#[derive(Debug, Clone, clap::Args)]
+pub struct AuthArgs {
+    /// The shared secret the API gateway sends in the `X-API-KEY` header when reporting credential
+    /// usage and introspecting access tokens.
+    #[arg(long, env)]
+    pub gateway_api_key: String,
+
+    /// The shared secret Hydra sends in the `X-API-KEY` header when calling the token hook, as
+    /// configured in `oauth2.token_hook.auth`.
+    #[arg(long, env)]
+    pub token_hook_api_key: String,
+}
+
+/// Rejects requests that do not carry the expected shared secret in the `X-API-KEY` header with
+/// `401 Unauthorized`
+#[derive(Clone)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    #[must_use]
+    pub fn new(key: String) -> Self {
+        Self(key)
+    }
+}
+
+impl<E: Endpoint> Middleware<E> for ApiKey {
+    type Output = ApiKeyEndpoint<E>;
+
+    fn transform(&self, inner: E) -> Self::Output {
+        ApiKeyEndpoint {
+            inner,
+            key: self.0.clone(),
+        }
+    }
+}
+
+/// An endpoint wrapped by [`ApiKey`]
+pub struct ApiKeyEndpoint<E> {
+    inner: E,
+    key: String,
+}
+
+#[async_trait]
+impl<E: Endpoint> Endpoint for ApiKeyEndpoint<E> {
+    type Output = E::Output;
+
+    async fn call(&self, req: Request) -> Result<Self::Output> {
+        let given = req
+            .headers()
+            .get(API_KEY_HEADER)
+            .map(|value| value.as_bytes())
+            .unwrap_or_default();
+
+        if !constant_time_eq(given, self.key.as_bytes()) {
+            return Err(Error::from_status(StatusCode::UNAUTHORIZED));
+        }
+
+        self.inner.call(req).await
+    }
+}
+
+/// Compares two byte strings in time independent of where they differ, so the key cannot be
+/// guessed byte by byte.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{handler, test::TestClient, EndpointExt};
+
+    use super::*;
+
+    #[handler]
+    fn ok() {}
+
+    #[tokio::test]
+    async fn requires_api_key() {
+        let client = TestClient::new(ok.with(ApiKey::new("secret".to_string())));
+
+        client
+            .get("/")
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+
+        client
+            .get("/")
+            .header(API_KEY_HEADER, "secreT")
+            .send()
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+
+        client
+            .get("/")
+            .header(API_KEY_HEADER, "secret")
+            .send()
+            .await
+            .assert_status_is_ok();
+    }
+}
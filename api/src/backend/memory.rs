@@ -0,0 +1,604 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{Mutex, MutexGuard},
+};
+
+use hub_core::{chrono::Utc, uuid::Uuid};
+use ory_openapi_generated_client::{
+    apis::{
+        o_auth2_api::{
+            CreateOAuth2ClientError, DeleteOAuth2ClientError, DeleteOAuth2TokenError,
+            GetOAuth2ClientError, IntrospectOAuth2TokenError, ListOAuth2ClientsError,
+            Oauth2TokenExchangeError, PatchOAuth2ClientError, RevokeOAuth2TokenError,
+            SetOAuth2ClientError,
+        },
+        Error, ResponseContent,
+    },
+    models::{IntrospectedOAuth2Token, JsonPatch, OAuth2Client, OAuth2TokenExchange},
+};
+use poem::async_trait;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+
+use super::CredentialBackend;
+use crate::ory_client::{generate_secret, parse_lifespan, ClientPage, CLIENT_CREDENTIALS_GRANT};
+
+/// The lifetime, in seconds, of tokens issued to clients without one, matching Hydra's default.
+const DEFAULT_TOKEN_LIFETIME: i64 = 3600;
+
+/// The page size used when listing clients without one, matching Hydra's default.
+const DEFAULT_PAGE_SIZE: i64 = 250;
+
+/// An access token issued by the in-memory backend
+#[derive(Debug, Clone)]
+struct Token {
+    client_id: String,
+    scope: Option<String>,
+    issued_at: i64,
+    expires_at: i64,
+}
+
+/// A failure the in-memory backend can be made to inject, so tests can exercise how callers
+/// handle it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fault {
+    /// Client creation fails with a server error.
+    CreateClient,
+    /// Token exchanges fail with a server error.
+    ExchangeToken,
+    /// Token exchanges succeed without returning an access token.
+    MalformedTokenExchange,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Clients by client ID, stored without their secret.
+    clients: BTreeMap<String, OAuth2Client>,
+    secrets: HashMap<String, String>,
+    tokens: HashMap<String, Token>,
+    faults: HashSet<Fault>,
+}
+
+/// An in-process stand-in for Hydra keeping clients and tokens in memory. It issues client IDs,
+/// secrets and opaque access tokens, filters and paginates clients by owner, and answers with the
+/// same error statuses as Hydra so callers cannot tell the two apart.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    state: Mutex<State>,
+}
+
+impl MemoryBackend {
+    fn state(&self) -> MutexGuard<'_, State> {
+        // the state is left consistent by every operation, so a panic elsewhere cannot corrupt it
+        self.state
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Makes every later request affected by `fault` fail.
+    pub fn inject(&self, fault: Fault) {
+        self.state().faults.insert(fault);
+    }
+
+    /// Stores `o_auth2_client` exactly as given, even when it is malformed, with `client_secret`
+    /// as its secret. A client ID is generated when it has none. Returns the client ID.
+    pub fn insert_client(&self, o_auth2_client: OAuth2Client, client_secret: &str) -> String {
+        let client_id = o_auth2_client
+            .client_id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        let mut state = self.state();
+        state.clients.insert(client_id.clone(), OAuth2Client {
+            client_id: Some(client_id.clone()),
+            client_secret: None,
+            ..o_auth2_client
+        });
+        state
+            .secrets
+            .insert(client_id.clone(), client_secret.to_string());
+
+        client_id
+    }
+
+    /// The number of stored clients, whatever their owner.
+    #[must_use]
+    pub fn client_count(&self) -> usize {
+        self.state().clients.len()
+    }
+}
+
+/// Builds an error response carrying an OAuth2 error body, as Hydra returns.
+fn error<T>(status: StatusCode, error: &str, description: &str) -> Error<T> {
+    Error::ResponseError(ResponseContent {
+        status,
+        content: json!({ "error": error, "error_description": description }).to_string(),
+        entity: None,
+    })
+}
+
+fn injected<T>() -> Error<T> {
+    error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "server_error",
+        "the fault was injected",
+    )
+}
+
+fn not_found<T>(client_id: &str) -> Error<T> {
+    error(
+        StatusCode::NOT_FOUND,
+        "not_found",
+        &format!("client {client_id} does not exist"),
+    )
+}
+
+/// Checks the client credentials the way Hydra's token endpoints do.
+fn authenticate<T>(state: &State, client_id: &str, client_secret: &str) -> Result<(), Error<T>> {
+    match state.secrets.get(client_id) {
+        Some(secret) if secret == client_secret => Ok(()),
+        _ => Err(error(
+            StatusCode::UNAUTHORIZED,
+            "invalid_client",
+            "client authentication failed",
+        )),
+    }
+}
+
+/// Applies JSON Patch `add`, `replace` and `remove` operations to `value`.
+fn apply_patch(value: &mut Value, patch: Vec<JsonPatch>) -> Result<(), String> {
+    for JsonPatch {
+        op,
+        path,
+        value: new,
+        ..
+    } in patch
+    {
+        let (parent, key) = path
+            .rsplit_once('/')
+            .ok_or_else(|| format!("invalid path {path:?}"))?;
+
+        let target = value
+            .pointer_mut(parent)
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| format!("path {parent:?} does not exist"))?;
+
+        match op.as_str() {
+            "add" | "replace" => {
+                target.insert(key.to_string(), new.unwrap_or_default());
+            },
+            "remove" => {
+                target.remove(key);
+            },
+            op => return Err(format!("unsupported operation {op:?}")),
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl CredentialBackend for MemoryBackend {
+    async fn create_client(
+        &self,
+        o_auth2_client: &OAuth2Client,
+    ) -> Result<OAuth2Client, Error<CreateOAuth2ClientError>> {
+        if self.state().faults.contains(&Fault::CreateClient) {
+            return Err(injected());
+        }
+
+        let client_id = Uuid::new_v4().to_string();
+        let client_secret = o_auth2_client
+            .client_secret
+            .clone()
+            .unwrap_or_else(generate_secret);
+
+        let stored = OAuth2Client {
+            client_id: Some(client_id.clone()),
+            client_secret: None,
+            created_at: Some(Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()),
+            ..o_auth2_client.clone()
+        };
+
+        let mut state = self.state();
+        state.clients.insert(client_id.clone(), stored.clone());
+        state.secrets.insert(client_id, client_secret.clone());
+
+        Ok(OAuth2Client {
+            client_secret: Some(client_secret),
+            ..stored
+        })
+    }
+
+    async fn update_client(
+        &self,
+        id: &str,
+        o_auth2_client: &OAuth2Client,
+    ) -> Result<OAuth2Client, Error<SetOAuth2ClientError>> {
+        let mut state = self.state();
+
+        let created_at = state
+            .clients
+            .get(id)
+            .ok_or_else(|| not_found(id))?
+            .created_at
+            .clone();
+
+        if let Some(secret) = o_auth2_client.client_secret.clone() {
+            state.secrets.insert(id.to_string(), secret);
+        }
+
+        let stored = OAuth2Client {
+            client_id: Some(id.to_string()),
+            client_secret: None,
+            created_at,
+            ..o_auth2_client.clone()
+        };
+
+        state.clients.insert(id.to_string(), stored.clone());
+
+        Ok(stored)
+    }
+
+    async fn patch_client(
+        &self,
+        id: &str,
+        patch: Vec<JsonPatch>,
+    ) -> Result<OAuth2Client, Error<PatchOAuth2ClientError>> {
+        let mut state = self.state();
+
+        let current = state.clients.get(id).ok_or_else(|| not_found(id))?;
+        let mut value = serde_json::to_value(current)?;
+
+        apply_patch(&mut value, patch)
+            .map_err(|e| error(StatusCode::BAD_REQUEST, "invalid_request", &e))?;
+
+        let mut patched: OAuth2Client = serde_json::from_value(value)?;
+        patched.client_id = Some(id.to_string());
+
+        if let Some(secret) = patched.client_secret.take() {
+            state.secrets.insert(id.to_string(), secret);
+        }
+
+        state.clients.insert(id.to_string(), patched.clone());
+
+        Ok(patched)
+    }
+
+    async fn get_client(
+        &self,
+        client_id: &str,
+    ) -> Result<OAuth2Client, Error<GetOAuth2ClientError>> {
+        self.state()
+            .clients
+            .get(client_id)
+            .cloned()
+            .ok_or_else(|| not_found(client_id))
+    }
+
+    async fn delete_client(&self, client_id: &str) -> Result<(), Error<DeleteOAuth2ClientError>> {
+        let mut state = self.state();
+
+        state
+            .clients
+            .remove(client_id)
+            .ok_or_else(|| not_found(client_id))?;
+        state.secrets.remove(client_id);
+        state.tokens.retain(|_, token| token.client_id != client_id);
+
+        Ok(())
+    }
+
+    async fn revoke_tokens(&self, client_id: &str) -> Result<(), Error<DeleteOAuth2TokenError>> {
+        self.state()
+            .tokens
+            .retain(|_, token| token.client_id != client_id);
+
+        Ok(())
+    }
+
+    async fn list_clients(
+        &self,
+        owner: &str,
+        page_size: Option<i64>,
+        page_token: Option<&str>,
+    ) -> Result<Vec<OAuth2Client>, Error<ListOAuth2ClientsError>> {
+        let page = self
+            .list_clients_page(owner, page_size.unwrap_or(DEFAULT_PAGE_SIZE), page_token)
+            .await?;
+
+        Ok(page.clients)
+    }
+
+    /// Pages through the clients of `owner` ordered by client ID. The page token is the client ID
+    /// of the last client on the previous page.
+    async fn list_clients_page(
+        &self,
+        owner: &str,
+        page_size: i64,
+        page_token: Option<&str>,
+    ) -> Result<ClientPage, Error<ListOAuth2ClientsError>> {
+        let page_size = usize::try_from(page_size)
+            .ok()
+            .filter(|size| *size > 0)
+            .ok_or_else(|| {
+                error(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_request",
+                    "page size must be positive",
+                )
+            })?;
+
+        let state = self.state();
+
+        let owned = state
+            .clients
+            .values()
+            .filter(|c| c.owner.as_deref() == Some(owner))
+            .collect::<Vec<_>>();
+
+        let mut clients = owned
+            .iter()
+            .filter(|c| page_token.map_or(true, |token| c.client_id.as_deref() > Some(token)))
+            .take(page_size + 1)
+            .map(|c| (*c).clone())
+            .collect::<Vec<_>>();
+
+        let next_page_token = if clients.len() > page_size {
+            clients.truncate(page_size);
+            clients.last().and_then(|c| c.client_id.clone())
+        } else {
+            None
+        };
+
+        Ok(ClientPage {
+            clients,
+            next_page_token,
+            total_count: i64::try_from(owned.len()).ok(),
+        })
+    }
+
+    async fn exchange_token(
+        &self,
+        client_id: String,
+        client_secret: String,
+        scope: Option<&str>,
+    ) -> Result<OAuth2TokenExchange, Error<Oauth2TokenExchangeError>> {
+        let mut state = self.state();
+
+        if state.faults.contains(&Fault::ExchangeToken) {
+            return Err(injected());
+        }
+
+        authenticate(&state, &client_id, &client_secret)?;
+
+        let o_auth2_client = state
+            .clients
+            .get(&client_id)
+            .ok_or_else(|| not_found(&client_id))?;
+
+        let enabled = o_auth2_client.grant_types.as_ref().map_or(false, |grants| {
+            grants.iter().any(|g| g == CLIENT_CREDENTIALS_GRANT)
+        });
+
+        if !enabled {
+            return Err(error(
+                StatusCode::BAD_REQUEST,
+                "unauthorized_client",
+                "the client is not allowed to use the client credentials grant",
+            ));
+        }
+
+        let allowed = o_auth2_client.scope.clone().unwrap_or_default();
+        let scope = match scope {
+            Some(scope) => {
+                if let Some(denied) = scope
+                    .split_whitespace()
+                    .find(|s| !allowed.split_whitespace().any(|a| a == *s))
+                {
+                    return Err(error(
+                        StatusCode::BAD_REQUEST,
+                        "invalid_scope",
+                        &format!("the client is not allowed to request scope {denied:?}"),
+                    ));
+                }
+
+                scope.to_string()
+            },
+            None => allowed,
+        };
+
+        let lifetime = o_auth2_client
+            .client_credentials_grant_access_token_lifespan
+            .as_deref()
+            .filter(|lifespan| !lifespan.is_empty())
+            .and_then(|lifespan| parse_lifespan(lifespan).ok())
+            .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+
+        if state.faults.contains(&Fault::MalformedTokenExchange) {
+            return Ok(OAuth2TokenExchange {
+                token_type: Some("bearer".to_string()),
+                ..OAuth2TokenExchange::new()
+            });
+        }
+
+        let access_token = generate_secret();
+        let issued_at = Utc::now().timestamp();
+
+        state.tokens.insert(access_token.clone(), Token {
+            client_id,
+            scope: Some(scope.clone()).filter(|s| !s.is_empty()),
+            issued_at,
+            expires_at: issued_at + lifetime,
+        });
+
+        Ok(OAuth2TokenExchange {
+            access_token: Some(access_token),
+            expires_in: Some(lifetime),
+            scope: Some(scope),
+            token_type: Some("bearer".to_string()),
+            ..OAuth2TokenExchange::new()
+        })
+    }
+
+    async fn revoke_token(
+        &self,
+        client_id: String,
+        client_secret: String,
+        token: &str,
+    ) -> Result<(), Error<RevokeOAuth2TokenError>> {
+        let mut state = self.state();
+
+        authenticate(&state, &client_id, &client_secret)?;
+
+        // revoking an unknown token succeeds without effect, as in RFC 7009
+        if state
+            .tokens
+            .get(token)
+            .map_or(false, |t| t.client_id == client_id)
+        {
+            state.tokens.remove(token);
+        }
+
+        Ok(())
+    }
+
+    async fn introspect_token(
+        &self,
+        token: &str,
+    ) -> Result<IntrospectedOAuth2Token, Error<IntrospectOAuth2TokenError>> {
+        let now = Utc::now().timestamp();
+
+        let introspected = match self.state().tokens.get(token) {
+            Some(token) if token.expires_at > now => IntrospectedOAuth2Token {
+                client_id: Some(token.client_id.clone()),
+                scope: token.scope.clone(),
+                iat: Some(token.issued_at),
+                exp: Some(token.expires_at),
+                token_type: Some("Bearer".to_string()),
+                token_use: Some("access_token".to_string()),
+                ..IntrospectedOAuth2Token::new(true)
+            },
+            Some(_) | None => IntrospectedOAuth2Token::new(false),
+        };
+
+        Ok(introspected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ory_client::add_patch;
+
+    fn client(owner: &str) -> OAuth2Client {
+        OAuth2Client {
+            client_name: Some("test".to_string()),
+            owner: Some(owner.to_string()),
+            scope: Some("drops:read drops:write".to_string()),
+            grant_types: Some(vec![CLIENT_CREDENTIALS_GRANT.to_string()]),
+            ..OAuth2Client::new()
+        }
+    }
+
+    #[tokio::test]
+    async fn issues_secret_and_hides_it_afterwards() {
+        let backend = MemoryBackend::default();
+
+        let created = backend.create_client(&client("org")).await.unwrap();
+        let client_id = created.client_id.unwrap();
+
+        assert!(created.client_secret.is_some());
+        assert!(created.created_at.is_some());
+
+        let fetched = backend.get_client(&client_id).await.unwrap();
+
+        assert_eq!(fetched.client_secret, None);
+    }
+
+    #[tokio::test]
+    async fn pages_through_clients_of_owner() {
+        let backend = MemoryBackend::default();
+
+        for _ in 0..5 {
+            backend.create_client(&client("org")).await.unwrap();
+        }
+        backend.create_client(&client("other")).await.unwrap();
+
+        let first = backend.list_clients_page("org", 2, None).await.unwrap();
+
+        assert_eq!(first.clients.len(), 2);
+        assert_eq!(first.total_count, Some(5));
+
+        let all = backend.list_all_clients("org").await.unwrap();
+
+        assert_eq!(all.len(), 5);
+        assert!(all.iter().all(|c| c.owner.as_deref() == Some("org")));
+    }
+
+    #[tokio::test]
+    async fn exchanges_and_introspects_tokens() {
+        let backend = MemoryBackend::default();
+
+        let created = backend.create_client(&client("org")).await.unwrap();
+        let client_id = created.client_id.unwrap();
+        let secret = created.client_secret.unwrap();
+
+        let denied = backend
+            .exchange_token(client_id.clone(), secret.clone(), Some("wallets:write"))
+            .await;
+
+        assert!(
+            matches!(denied, Err(Error::ResponseError(res)) if res.status == StatusCode::BAD_REQUEST)
+        );
+
+        let exchanged = backend
+            .exchange_token(client_id.clone(), secret.clone(), Some("drops:read"))
+            .await
+            .unwrap();
+        let token = exchanged.access_token.unwrap();
+
+        let introspected = backend.introspect_token(&token).await.unwrap();
+
+        assert!(introspected.active);
+        assert_eq!(introspected.client_id, Some(client_id.clone()));
+        assert_eq!(introspected.scope.as_deref(), Some("drops:read"));
+
+        backend
+            .revoke_token(client_id, secret, &token)
+            .await
+            .unwrap();
+
+        assert!(!backend.introspect_token(&token).await.unwrap().active);
+    }
+
+    #[tokio::test]
+    async fn rotates_secret_and_suspends_through_patches() {
+        let backend = MemoryBackend::default();
+
+        let created = backend.create_client(&client("org")).await.unwrap();
+        let client_id = created.client_id.unwrap();
+        let old_secret = created.client_secret.unwrap();
+
+        let rotated = backend.rotate_client_secret(&client_id).await.unwrap();
+        let new_secret = rotated.client_secret.unwrap();
+
+        assert!(backend
+            .exchange_token(client_id.clone(), old_secret, None)
+            .await
+            .is_err());
+
+        backend
+            .patch_client(&client_id, vec![add_patch("/grant_types", json!([]))])
+            .await
+            .unwrap();
+
+        let suspended = backend.get_client(&client_id).await.unwrap();
+
+        assert_eq!(suspended.grant_types, Some(vec![]));
+        assert!(backend
+            .exchange_token(client_id, new_secret, None)
+            .await
+            .is_err());
+    }
+}
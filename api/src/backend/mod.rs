@@ -0,0 +1,210 @@
+mod memory;
+
+use std::{fmt, sync::Arc};
+
+use hub_core::clap;
+use ory_openapi_generated_client::{
+    apis::{
+        o_auth2_api::{
+            CreateOAuth2ClientError, DeleteOAuth2ClientError, DeleteOAuth2TokenError,
+            GetOAuth2ClientError, IntrospectOAuth2TokenError, ListOAuth2ClientsError,
+            Oauth2TokenExchangeError, PatchOAuth2ClientError, RevokeOAuth2TokenError,
+            SetOAuth2ClientError,
+        },
+        Error,
+    },
+    models::{IntrospectedOAuth2Token, JsonPatch, OAuth2Client, OAuth2TokenExchange},
+};
+use poem::async_trait;
+
+pub use self::memory::{Fault, MemoryBackend};
+use crate::ory_client::{self, generate_secret, ClientPage, OryArgs, MAX_PAGE_SIZE};
+
+/// The credential backend shared by the API, the event consumer and the background tasks.
+pub type Backend = Arc<dyn CredentialBackend>;
+
+/// Arguments for choosing where OAuth2 clients and their tokens are kept
+#[derive(Debug, Clone, clap::Args)]
+pub struct BackendArgs {
+    /// The backend storing OAuth2 clients and issuing their tokens.
+    #[arg(long, env, value_enum, default_value_t = BackendKind::Ory)]
+    pub credential_backend: BackendKind,
+}
+
+/// A store of OAuth2 clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum BackendKind {
+    /// Ory Hydra, reached through its admin and public APIs.
+    Ory,
+    /// An in-process stand-in for Hydra that forgets every client on restart. Meant for local
+    /// development and tests only.
+    Memory,
+}
+
+/// Builds the backend selected by `args`, pointing the Ory client at `ory` when it is chosen.
+#[must_use]
+pub fn build(args: BackendArgs, ory: OryArgs) -> Backend {
+    let BackendArgs { credential_backend } = args;
+
+    match credential_backend {
+        BackendKind::Ory => Arc::new(ory_client::Client::new(ory)),
+        BackendKind::Memory => Arc::new(MemoryBackend::default()),
+    }
+}
+
+/// Stores the OAuth2 clients behind API credentials and issues, revokes and introspects their
+/// access tokens. Errors use the types of the generated Ory client so callers can inspect the
+/// response status whichever backend is in use.
+#[async_trait]
+pub trait CredentialBackend: fmt::Debug + Send + Sync {
+    /// Creates a client, returning it with its generated client ID and secret.
+    ///
+    /// # Errors
+    /// Returns an error if the client is rejected or the backend is unavailable.
+    async fn create_client(
+        &self,
+        o_auth2_client: &OAuth2Client,
+    ) -> Result<OAuth2Client, Error<CreateOAuth2ClientError>>;
+
+    /// Replaces every field of the client `id`.
+    ///
+    /// # Errors
+    /// Returns an error if the client does not exist or the backend is unavailable.
+    async fn update_client(
+        &self,
+        id: &str,
+        o_auth2_client: &OAuth2Client,
+    ) -> Result<OAuth2Client, Error<SetOAuth2ClientError>>;
+
+    /// Applies a JSON Patch to the client, leaving every field the patch does not touch as it is.
+    ///
+    /// # Errors
+    /// Returns an error if the client does not exist or the patch does not apply.
+    async fn patch_client(
+        &self,
+        id: &str,
+        patch: Vec<JsonPatch>,
+    ) -> Result<OAuth2Client, Error<PatchOAuth2ClientError>>;
+
+    /// Reads a single client.
+    ///
+    /// # Errors
+    /// Returns an error if the client does not exist or the backend is unavailable.
+    async fn get_client(
+        &self,
+        client_id: &str,
+    ) -> Result<OAuth2Client, Error<GetOAuth2ClientError>>;
+
+    /// Deletes a client.
+    ///
+    /// # Errors
+    /// Returns an error if the client does not exist or the backend is unavailable.
+    async fn delete_client(&self, client_id: &str) -> Result<(), Error<DeleteOAuth2ClientError>>;
+
+    /// Revokes every access token issued to the client.
+    ///
+    /// # Errors
+    /// Returns an error if the backend is unavailable.
+    async fn revoke_tokens(&self, client_id: &str) -> Result<(), Error<DeleteOAuth2TokenError>>;
+
+    /// Lists the clients owned by `owner`.
+    ///
+    /// # Errors
+    /// Returns an error if the backend is unavailable.
+    async fn list_clients(
+        &self,
+        owner: &str,
+        page_size: Option<i64>,
+        page_token: Option<&str>,
+    ) -> Result<Vec<OAuth2Client>, Error<ListOAuth2ClientsError>>;
+
+    /// Lists a single page of the clients owned by `owner`, including the token of the following
+    /// page.
+    ///
+    /// # Errors
+    /// Returns an error if the backend is unavailable.
+    async fn list_clients_page(
+        &self,
+        owner: &str,
+        page_size: i64,
+        page_token: Option<&str>,
+    ) -> Result<ClientPage, Error<ListOAuth2ClientsError>>;
+
+    /// Exchanges the client credentials for an access token, requesting `scope` when given.
+    ///
+    /// # Errors
+    /// Returns an error if the credentials or scope are rejected.
+    async fn exchange_token(
+        &self,
+        client_id: String,
+        client_secret: String,
+        scope: Option<&str>,
+    ) -> Result<OAuth2TokenExchange, Error<Oauth2TokenExchangeError>>;
+
+    /// Revokes a single access token issued to the client.
+    ///
+    /// # Errors
+    /// Returns an error if the client credentials are rejected.
+    async fn revoke_token(
+        &self,
+        client_id: String,
+        client_secret: String,
+        token: &str,
+    ) -> Result<(), Error<RevokeOAuth2TokenError>>;
+
+    /// Reports whether `token` is active and who it was issued to.
+    ///
+    /// # Errors
+    /// Returns an error if the backend is unavailable.
+    async fn introspect_token(
+        &self,
+        token: &str,
+    ) -> Result<IntrospectedOAuth2Token, Error<IntrospectOAuth2TokenError>>;
+
+    /// Replaces the secret of an existing client with a newly generated one. The plain-text secret
+    /// is set on the returned client.
+    ///
+    /// # Errors
+    /// Returns an error if the client does not exist or the patch is rejected.
+    async fn rotate_client_secret(
+        &self,
+        client_id: &str,
+    ) -> Result<OAuth2Client, Error<PatchOAuth2ClientError>> {
+        let client_secret = generate_secret();
+
+        let patch = vec![JsonPatch {
+            value: Some(client_secret.clone().into()),
+            ..JsonPatch::new("replace".to_string(), "/client_secret".to_string())
+        }];
+
+        let mut o_auth2_client = self.patch_client(client_id, patch).await?;
+        o_auth2_client.client_secret = Some(client_secret);
+
+        Ok(o_auth2_client)
+    }
+
+    /// Lists every client owned by `owner`, following page tokens across pages.
+    ///
+    /// # Errors
+    /// Returns an error if any page cannot be listed.
+    async fn list_all_clients(
+        &self,
+        owner: &str,
+    ) -> Result<Vec<OAuth2Client>, Error<ListOAuth2ClientsError>> {
+        let mut clients = Vec::new();
+        let mut page_token = None;
+
+        loop {
+            let page = self
+                .list_clients_page(owner, MAX_PAGE_SIZE, page_token.as_deref())
+                .await?;
+
+            clients.extend(page.clients);
+
+            match page.next_page_token {
+                Some(next) if !next.is_empty() => page_token = Some(next),
+                _ => return Ok(clients),
+            }
+        }
+    }
+}
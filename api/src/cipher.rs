@@ -0,0 +1,73 @@
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use hub_core::{anyhow::Result, clap, prelude::*};
+
+/// The length in bytes of the nonce prefixed to every ciphertext.
+const NONCE_LENGTH: usize = 12;
+
+/// Arguments for encrypting the secrets kept to revoke access tokens later
+#[derive(Debug, Clone, clap::Args)]
+pub struct CipherArgs {
+    /// The hex encoded 256-bit key used to encrypt the access tokens and client secrets of pending
+    /// token revocations.
+    #[arg(long, env)]
+    pub token_revocation_encryption_key: String,
+}
+
+/// Encrypts the secrets this service keeps at rest with AES-256-GCM
+#[derive(Clone)]
+pub struct Cipher(Aes256Gcm);
+
+impl Cipher {
+    /// Builds the cipher of pending token revocations from its configuration.
+    ///
+    /// # Errors
+    /// Returns an error if the key is not 32 hex encoded bytes.
+    pub fn from_args(args: &CipherArgs) -> Result<Self> {
+        Self::new(&args.token_revocation_encryption_key)
+    }
+
+    /// Builds the cipher from a hex encoded 256-bit key.
+    ///
+    /// # Errors
+    /// Returns an error if the key is not 32 hex encoded bytes.
+    pub fn new(key: &str) -> Result<Self> {
+        let key = hex::decode(key.trim())?;
+        let cipher = Aes256Gcm::new_from_slice(&key)
+            .map_err(|_| anyhow!("encryption key must be 32 bytes"))?;
+
+        Ok(Self(cipher))
+    }
+
+    /// Encrypts `plaintext` under a fresh nonce, which is prefixed to the returned ciphertext.
+    ///
+    /// # Errors
+    /// Returns an error if encryption fails.
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .0
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow!("failed to encrypt"))?;
+
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    /// Decrypts a ciphertext produced by [`Cipher::encrypt`].
+    ///
+    /// # Errors
+    /// Returns an error if the ciphertext is truncated or was not encrypted with this key.
+    pub fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        if ciphertext.len() < NONCE_LENGTH {
+            return Err(anyhow!("ciphertext is too short"));
+        }
+
+        let (nonce, ciphertext) = ciphertext.split_at(NONCE_LENGTH);
+
+        self.0
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("failed to decrypt"))
+    }
+}
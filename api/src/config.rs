@@ -0,0 +1,191 @@
+use std::ops::RangeInclusive;
+
+use hub_core::{
+    anyhow::{Error, Result},
+    chrono::Duration,
+    clap,
+    prelude::*,
+    uuid::Uuid,
+};
+
+/// Arguments controlling the lifecycle of API credentials
+#[derive(Debug, Clone, clap::Args)]
+pub struct CredentialArgs {
+    /// The lifetime, in seconds, of access tokens issued to credentials created without one. It is
+    /// clamped into the token lifetime limits of the credential's organization.
+    #[arg(long, env, default_value_t = 31_536_000)]
+    pub token_lifetime_default: i64,
+
+    /// The shortest token lifetime, in seconds, a credential may be given.
+    #[arg(long, env, default_value_t = 300)]
+    pub token_lifetime_min: i64,
+
+    /// The longest token lifetime, in seconds, a credential may be given.
+    #[arg(long, env, default_value_t = 31_536_000)]
+    pub token_lifetime_max: i64,
+
+    /// Token lifetime limits for individual organizations, overriding the service-wide limits.
+    /// Given as a comma-separated list of `<organization id>=<min>:<max>` in seconds.
+    #[arg(long, env, value_delimiter = ',')]
+    pub organization_token_lifetime_limits: Vec<OrganizationTokenLifetimeLimits>,
+
+    /// The longest time, in days, a credential may live before it expires. Credentials may be
+    /// created without an expiry when unset.
+    #[arg(long, env)]
+    pub credential_lifetime_max_days: Option<i64>,
+
+    /// Credential lifetime limits for individual organizations, overriding the service-wide limit.
+    /// Given as a comma-separated list of `<organization id>=<days>`.
+    #[arg(long, env, value_delimiter = ',')]
+    pub organization_credential_lifetime_max_days: Vec<OrganizationCredentialLifetime>,
+
+    /// The number of active credentials an organization may have.
+    #[arg(long, env, default_value_t = 100)]
+    pub credential_limit_default: u64,
+
+    /// Active credential limits for individual organizations, overriding the service default.
+    /// Given as a comma-separated list of `<organization id>=<limit>`.
+    #[arg(long, env, value_delimiter = ',')]
+    pub organization_credential_limits: Vec<OrganizationCredentialLimit>,
+
+    /// Disable the credentials created by a member when they are removed from the organization.
+    #[arg(long, env, default_value_t = false)]
+    pub disable_credentials_of_removed_members: bool,
+}
+
+impl CredentialArgs {
+    /// The inclusive range of token lifetimes, in seconds, credentials of `organization` may be
+    /// given.
+    #[must_use]
+    pub fn token_lifetime_limits(&self, organization: Uuid) -> RangeInclusive<i64> {
+        self.organization_token_lifetime_limits
+            .iter()
+            .find(|limits| limits.organization == organization)
+            .map_or(
+                self.token_lifetime_min..=self.token_lifetime_max,
+                |limits| limits.min..=limits.max,
+            )
+    }
+
+    /// The token lifetime, in seconds, given to credentials of `organization` created without one.
+    #[must_use]
+    pub fn default_token_lifetime(&self, organization: Uuid) -> i64 {
+        let limits = self.token_lifetime_limits(organization);
+
+        // unlike `clamp`, this cannot panic on misconfigured service-wide limits
+        self.token_lifetime_default
+            .max(*limits.start())
+            .min(*limits.end())
+    }
+
+    /// The number of active credentials `organization` may have.
+    #[must_use]
+    pub fn credential_limit(&self, organization: Uuid) -> u64 {
+        self.organization_credential_limits
+            .iter()
+            .find(|limit| limit.organization == organization)
+            .map_or(self.credential_limit_default, |limit| limit.limit)
+    }
+
+    /// The longest time credentials of `organization` may live before they expire, if limited.
+    #[must_use]
+    pub fn credential_lifetime_max(&self, organization: Uuid) -> Option<Duration> {
+        self.organization_credential_lifetime_max_days
+            .iter()
+            .find(|lifetime| lifetime.organization == organization)
+            .map_or(self.credential_lifetime_max_days, |lifetime| {
+                Some(lifetime.max_days)
+            })
+            .map(Duration::days)
+    }
+}
+
+/// Token lifetime limits applying to the credentials of a single organization
+#[derive(Debug, Clone, Copy)]
+pub struct OrganizationTokenLifetimeLimits {
+    pub organization: Uuid,
+    pub min: i64,
+    pub max: i64,
+}
+
+impl FromStr for OrganizationTokenLifetimeLimits {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (organization, limits) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected <organization id>=<min>:<max>, got {s:?}"))?;
+        let (min, max) = limits
+            .split_once(':')
+            .ok_or_else(|| anyhow!("expected <min>:<max>, got {limits:?}"))?;
+
+        let organization = Uuid::from_str(organization.trim())?;
+        let min: i64 = min.trim().parse()?;
+        let max: i64 = max.trim().parse()?;
+
+        if min > max {
+            return Err(anyhow!(
+                "token lifetime minimum {min} exceeds maximum {max} for {organization}"
+            ));
+        }
+
+        Ok(Self {
+            organization,
+            min,
+            max,
+        })
+    }
+}
+
+/// The credential lifetime limit applying to the credentials of a single organization
+#[derive(Debug, Clone, Copy)]
+pub struct OrganizationCredentialLifetime {
+    pub organization: Uuid,
+    pub max_days: i64,
+}
+
+impl FromStr for OrganizationCredentialLifetime {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (organization, max_days) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected <organization id>=<days>, got {s:?}"))?;
+
+        let organization = Uuid::from_str(organization.trim())?;
+        let max_days: i64 = max_days.trim().parse()?;
+
+        if max_days <= 0 {
+            return Err(anyhow!(
+                "credential lifetime {max_days} for {organization} must be positive"
+            ));
+        }
+
+        Ok(Self {
+            organization,
+            max_days,
+        })
+    }
+}
+
+/// The active credential limit applying to a single organization
+#[derive(Debug, Clone, Copy)]
+pub struct OrganizationCredentialLimit {
+    pub organization: Uuid,
+    pub limit: u64,
+}
+
+impl FromStr for OrganizationCredentialLimit {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (organization, limit) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("expected <organization id>=<limit>, got {s:?}"))?;
+
+        Ok(Self {
+            organization: Uuid::from_str(organization.trim())?,
+            limit: limit.trim().parse()?,
+        })
+    }
+}
@@ -0,0 +1,66 @@
+use std::{sync::Arc, time::Duration};
+
+use hub_core::{
+    anyhow::{Context, Result},
+    clap,
+};
+use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+
+/// Arguments for establishing a database connection
+#[derive(Debug, Clone, clap::Args)]
+pub struct DbArgs {
+    #[arg(long, env, default_value_t = 10)]
+    connection_timeout: u64,
+    #[arg(long, env, default_value_t = 10)]
+    acquire_timeout: u64,
+    #[arg(long, env, default_value_t = 60)]
+    idle_timeout: u64,
+    #[arg(long, env, default_value_t = 10)]
+    max_connections: u32,
+    #[arg(long, env)]
+    database_url: String,
+}
+
+/// A shared handle to the service's database
+#[derive(Debug, Clone)]
+pub struct Connection(Arc<DatabaseConnection>);
+
+impl Connection {
+    /// Connects to the database described by `args`.
+    ///
+    /// # Errors
+    /// Returns an error if the database cannot be reached.
+    pub async fn new(args: DbArgs) -> Result<Self> {
+        let DbArgs {
+            connection_timeout,
+            acquire_timeout,
+            idle_timeout,
+            max_connections,
+            database_url,
+        } = args;
+
+        let options = ConnectOptions::new(database_url)
+            .connect_timeout(Duration::from_secs(connection_timeout))
+            .acquire_timeout(Duration::from_secs(acquire_timeout))
+            .idle_timeout(Duration::from_secs(idle_timeout))
+            .max_connections(max_connections)
+            .clone();
+
+        let db = Database::connect(options)
+            .await
+            .context("failed to get database connection")?;
+
+        Ok(Self(Arc::new(db)))
+    }
+
+    #[must_use]
+    pub fn get(&self) -> &DatabaseConnection {
+        &self.0
+    }
+}
+
+impl From<DatabaseConnection> for Connection {
+    fn from(db: DatabaseConnection) -> Self {
+        Self(Arc::new(db))
+    }
+}
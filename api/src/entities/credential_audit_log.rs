@@ -0,0 +1,27 @@
+use sea_orm::entity::prelude::*;
+
+use super::sea_orm_active_enums::CredentialAuditAction;
+
+/// A change made to a credential, kept for auditing
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "credential_audit_log")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub organization_id: Uuid,
+    #[sea_orm(column_type = "Text")]
+    pub client_id: String,
+    pub action: CredentialAuditAction,
+    pub actor_id: Option<Uuid>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub ip: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub user_agent: Option<String>,
+    pub diff: Json,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,17 @@
+use sea_orm::entity::prelude::*;
+
+/// The most recent use of a credential reported by the API gateway
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "credential_usage")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false, column_type = "Text")]
+    pub client_id: String,
+    pub last_used_at: DateTime,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub last_used_ip: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,17 @@
+use sea_orm::entity::prelude::*;
+
+/// The number of requests made with a credential on a single day in UTC
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "credential_usage_buckets")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false, column_type = "Text")]
+    pub client_id: String,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub day: Date,
+    pub requests: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
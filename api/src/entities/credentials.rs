@@ -0,0 +1,32 @@
+use sea_orm::entity::prelude::*;
+
+use super::sea_orm_active_enums::CredentialStatus;
+
+/// The metadata of a credential owned by this service, kept alongside its Ory client
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "credentials")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false, column_type = "Text")]
+    pub client_id: String,
+    pub organization_id: Uuid,
+    #[sea_orm(column_type = "Text")]
+    pub name: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub description: Option<String>,
+    pub scopes: Vec<String>,
+    pub status: CredentialStatus,
+    pub created_by_id: Uuid,
+    pub created_at: DateTime,
+    pub updated_at: Option<DateTime>,
+    pub updated_by_id: Option<Uuid>,
+    pub deleted_by_id: Option<Uuid>,
+    pub deleted_at: Option<DateTime>,
+    pub expires_at: Option<DateTime>,
+    pub expiry_warning_days: Option<i32>,
+    pub client_deleted_at: Option<DateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
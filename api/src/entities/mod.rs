@@ -0,0 +1,11 @@
+pub mod prelude;
+
+pub mod credential_audit_log;
+pub mod credential_event_dead_letters;
+pub mod credential_event_outbox;
+pub mod credential_usage;
+pub mod credential_usage_buckets;
+pub mod credentials;
+pub mod idempotency_keys;
+pub mod sea_orm_active_enums;
+pub mod token_revocations;
@@ -0,0 +1,8 @@
+pub use super::{
+    credential_audit_log::Entity as CredentialAuditLog,
+    credential_event_dead_letters::Entity as CredentialEventDeadLetters,
+    credential_event_outbox::Entity as CredentialEventOutbox,
+    credential_usage::Entity as CredentialUsage,
+    credential_usage_buckets::Entity as CredentialUsageBuckets, credentials::Entity as Credentials,
+    idempotency_keys::Entity as IdempotencyKeys, token_revocations::Entity as TokenRevocations,
+};
@@ -0,0 +1,23 @@
+use sea_orm::entity::prelude::*;
+
+/// An access token to revoke once the shorter lifetime it was requested with elapses
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "token_revocations")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    #[sea_orm(column_type = "Text")]
+    pub client_id: String,
+    /// The token and the client secret needed to revoke it, encrypted.
+    pub credentials: Vec<u8>,
+    pub revoke_at: DateTime,
+    pub attempts: i32,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub last_error: Option<String>,
+    pub created_at: DateTime,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,228 @@
+use hub_core::{anyhow::Result, prelude::*, uuid::Uuid};
+use ory_openapi_generated_client::models::OAuth2Client;
+use sea_orm::TransactionTrait;
+
+use crate::{
+    audit::{self, Actor},
+    backend::Backend,
+    config::CredentialArgs,
+    db::Connection,
+    entities::sea_orm_active_enums::{CredentialAuditAction, CredentialStatus},
+    introspection::IntrospectionCache,
+    ory_client::CLIENT_CREDENTIALS_GRANT,
+    outbox, producer,
+    proto::{
+        credential_events::Event, organization_events::Event as OrganizationEvent,
+        OrganizationEventKey, OrganizationEvents,
+    },
+    repository, Services,
+};
+
+/// Res
+///
+/// # Errors
+/// This function fails if the Ory requests or database writes fail
+pub async fn process(
+    msg: Services,
+    db: Connection,
+    ory: Backend,
+    config: CredentialArgs,
+    introspection: IntrospectionCache,
+) -> Result<()> {
+    // match topics
+    match msg {
+        Services::Organizations(key, e) => {
+            process_organization_event(key, e, &db, &ory, &config).await
+        },
+        // events published by any replica mean cached introspections of the credential are stale
+        Services::Credentials(key, _) => {
+            introspection.invalidate(&key.id);
+
+            Ok(())
+        },
+    }
+}
+
+async fn process_organization_event(
+    key: OrganizationEventKey,
+    OrganizationEvents { event }: OrganizationEvents,
+    db: &Connection,
+    ory: &Backend,
+    config: &CredentialArgs,
+) -> Result<()> {
+    match event {
+        Some(OrganizationEvent::OrganizationDeleted(_)) => {
+            let organization = Uuid::from_str(&key.id)?;
+
+            delete_organization_credentials(organization, &key.user_id, db, ory).await
+        },
+        Some(OrganizationEvent::MemberDeactivated(member))
+            if config.disable_credentials_of_removed_members =>
+        {
+            let organization = Uuid::from_str(&member.organization_id)?;
+            let user = Uuid::from_str(&member.user_id)?;
+
+            disable_member_credentials(organization, user, &key.user_id, db, ory).await
+        },
+        Some(_) | None => Ok(()),
+    }
+}
+
+/// Deletes every credential owned by a deleted organization. A credential that cannot be deleted
+/// does not stop the others from being removed; the failures are reported together afterwards so
+/// the event can be replayed for the credentials that remain.
+async fn delete_organization_credentials(
+    organization: Uuid,
+    actor: &str,
+    db: &Connection,
+    ory: &Backend,
+) -> Result<()> {
+    let o_auth2_clients = ory.list_all_clients(&organization.to_string()).await?;
+    let mut failed = Vec::new();
+
+    for o_auth2_client in o_auth2_clients {
+        let client_id = o_auth2_client.client_id.clone().unwrap_or_default();
+
+        if let Err(e) = delete_credential(o_auth2_client, actor, db, ory).await {
+            error!(
+                "failed to delete credential {client_id} of deleted organization {organization}: \
+                 {e:?}"
+            );
+
+            failed.push(client_id);
+        }
+    }
+
+    if failed.is_empty() {
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        "failed to delete {} credentials of deleted organization {organization}: {}",
+        failed.len(),
+        failed.join(", ")
+    ))
+}
+
+/// Revokes the tokens of a credential of a deleted organization and deletes it, leaving a
+/// tombstone of its metadata. The client is disabled before the tombstone is committed and only
+/// removed from Hydra afterwards; a failed removal is retried by the expiry scheduler.
+async fn delete_credential(
+    o_auth2_client: OAuth2Client,
+    actor: &str,
+    db: &Connection,
+    ory: &Backend,
+) -> Result<()> {
+    let txn = db.get().begin().await?;
+
+    let credential = repository::load_credential(&txn, o_auth2_client).await?;
+    let client_id = credential.client_id.clone();
+
+    repository::delete(&txn, ory, &credential, Uuid::from_str(actor).ok()).await?;
+
+    let event = Event::Oauth2ClientDeleted(producer::payload(
+        actor,
+        &credential,
+        Some(&credential),
+        None,
+    ));
+
+    outbox::enqueue_event(&txn, event, &client_id, actor).await?;
+
+    audit::record(
+        &txn,
+        &event_actor(actor),
+        CredentialAuditAction::Deleted,
+        &credential,
+        audit::diff(Some(&credential), None),
+    )
+    .await?;
+
+    txn.commit().await?;
+
+    info!(
+        "deleted credential {client_id} of deleted organization {}",
+        credential.organization_id
+    );
+
+    if let Err(e) = repository::delete_client(db, ory, &client_id).await {
+        warn!("failed to remove client of deleted credential {client_id}: {e:?}");
+    }
+
+    Ok(())
+}
+
+/// Suspends the credentials created by a member removed from an organization. Credentials that
+/// are already disabled are skipped.
+async fn disable_member_credentials(
+    organization: Uuid,
+    user: Uuid,
+    actor: &str,
+    db: &Connection,
+    ory: &Backend,
+) -> Result<()> {
+    let user = user.to_string();
+
+    let o_auth2_clients = ory.list_all_clients(&organization.to_string()).await?;
+
+    for o_auth2_client in o_auth2_clients {
+        let created_by_user = o_auth2_client
+            .contacts
+            .as_ref()
+            .map_or(false, |contacts| contacts.contains(&user));
+        let is_enabled = o_auth2_client.grant_types.as_ref().map_or(false, |grants| {
+            grants.iter().any(|g| g == CLIENT_CREDENTIALS_GRANT)
+        });
+
+        if !created_by_user || !is_enabled {
+            continue;
+        }
+
+        let txn = db.get().begin().await?;
+
+        let before = repository::load_credential(&txn, o_auth2_client).await?;
+        let client_id = before.client_id.clone();
+
+        let after = repository::set_status(
+            &txn,
+            ory,
+            &before,
+            CredentialStatus::Suspended,
+            Uuid::from_str(actor).ok(),
+        )
+        .await?;
+
+        let event = Event::Oauth2ClientSuspended(producer::payload(
+            actor,
+            &after,
+            Some(&before),
+            Some(&after),
+        ));
+
+        outbox::enqueue_event(&txn, event, &client_id, actor).await?;
+
+        audit::record(
+            &txn,
+            &event_actor(actor),
+            CredentialAuditAction::Suspended,
+            &after,
+            audit::diff(Some(&before), Some(&after)),
+        )
+        .await?;
+
+        txn.commit().await?;
+
+        info!("disabled credential {client_id} of removed member {user}");
+    }
+
+    Ok(())
+}
+
+/// The user behind an organization event, who is recorded as the actor of the credential changes
+/// it causes.
+fn event_actor(actor: &str) -> Actor {
+    Actor {
+        user_id: Uuid::from_str(actor).ok(),
+        ..Actor::default()
+    }
+}
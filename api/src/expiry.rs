@@ -0,0 +1,387 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use hub_core::{
+    anyhow::Result,
+    chrono::{Duration as ChronoDuration, Utc},
+    clap,
+    prelude::*,
+    tokio,
+};
+use ory_openapi_generated_client::apis::Error as OryError;
+use sea_orm::TransactionTrait;
+
+use crate::{
+    audit::{self, Actor},
+    backend::Backend,
+    cipher::Cipher,
+    config::CredentialArgs,
+    db::Connection,
+    entities::{
+        credentials,
+        sea_orm_active_enums::{CredentialAuditAction, CredentialStatus},
+    },
+    graphql::objects::Credential,
+    outbox, producer,
+    proto::{self, credential_events::Event},
+    repository, revocation,
+};
+
+/// The advisory lock held by the scheduler while it processes expiring credentials, so only one
+/// replica deactivates them and sends warnings.
+const SCHEDULER_LOCK_ID: i64 = 0x6578_7069_7279;
+
+/// Arguments for deactivating credentials once they expire
+#[derive(Debug, Clone, clap::Args)]
+pub struct ExpiryArgs {
+    /// The number of seconds between checks for expiring credentials and access tokens due to be
+    /// revoked.
+    #[arg(long, env, default_value_t = 60)]
+    pub credential_expiry_poll_interval: u64,
+
+    /// The number of days before a credential expires its owners are warned, as a comma-separated
+    /// list.
+    #[arg(long, env, value_delimiter = ',', default_values_t = [14, 7, 1])]
+    pub credential_expiry_warning_days: Vec<i32>,
+
+    /// What happens to credentials once they expire.
+    #[arg(long, env, value_enum, default_value_t = ExpiryAction::Suspend)]
+    pub credential_expiry_action: ExpiryAction,
+}
+
+/// What happens to a credential once it expires
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ExpiryAction {
+    /// Suspend the credential so it can be reactivated once its expiry is extended.
+    Suspend,
+    /// Delete the credential.
+    Delete,
+}
+
+/// Deactivates credentials once they pass their expiry and warns their owners ahead of it with
+/// `CredentialExpiring` events. Expired credentials are acted on by the service itself, so
+/// `user_id` is empty on the events it sends. Access tokens issued with a shorter lifetime than
+/// their credential's are revoked on the same schedule.
+pub struct Scheduler {
+    db: Connection,
+    ory: Backend,
+    cipher: Cipher,
+    config: CredentialArgs,
+    poll_interval: Duration,
+    warning_days: Vec<i32>,
+    action: ExpiryAction,
+    backfilled: AtomicBool,
+}
+
+impl Scheduler {
+    #[must_use]
+    pub fn new(
+        db: Connection,
+        ory: Backend,
+        cipher: Cipher,
+        config: CredentialArgs,
+        args: ExpiryArgs,
+    ) -> Self {
+        let ExpiryArgs {
+            credential_expiry_poll_interval,
+            credential_expiry_warning_days,
+            credential_expiry_action,
+        } = args;
+
+        Self {
+            db,
+            ory,
+            cipher,
+            config,
+            poll_interval: Duration::from_secs(credential_expiry_poll_interval),
+            warning_days: credential_expiry_warning_days,
+            action: credential_expiry_action,
+            backfilled: AtomicBool::new(false),
+        }
+    }
+
+    /// Checks for expiring credentials every poll interval until the task is cancelled.
+    pub async fn run(self) {
+        loop {
+            if let Err(e) = self.tick().await {
+                error!("failed to process expiring credentials: {e:?}");
+            }
+
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+
+    async fn tick(&self) -> Result<()> {
+        let lock = self.db.get().begin().await?;
+
+        if outbox::try_lock(&lock, SCHEDULER_LOCK_ID).await? {
+            revocation::revoke_due(self.db.get(), &self.ory, &self.cipher).await?;
+
+            if !self.backfilled.load(Ordering::Relaxed) {
+                self.backfill_expiry().await?;
+                self.backfilled.store(true, Ordering::Relaxed);
+            }
+
+            self.expire_credentials().await?;
+            self.delete_clients().await?;
+            self.warn_owners().await?;
+        }
+
+        lock.commit().await?;
+
+        Ok(())
+    }
+
+    /// Gives credentials without an expiry one, their creation plus the credential lifetime limit
+    /// of their organization, so limits introduced after the credentials were created apply to
+    /// them as well. Credentials older than the limit expire no sooner than the longest warning
+    /// lead from now, so their owners get every warning before they are deactivated. Clients
+    /// created before their metadata was stored get a metadata row carrying the expiry. Limits
+    /// only change on restart, so this runs once per process.
+    async fn backfill_expiry(&self) -> Result<()> {
+        let lead = self.warning_days.iter().max().copied().unwrap_or_default();
+        let earliest = Utc::now().naive_utc() + ChronoDuration::days(lead.into());
+
+        let mut organizations = repository::organizations(self.db.get()).await?;
+        organizations.extend(
+            self.config
+                .organization_credential_lifetime_max_days
+                .iter()
+                .map(|lifetime| lifetime.organization),
+        );
+        organizations.sort_unstable();
+        organizations.dedup();
+
+        for organization in organizations {
+            let lifetime = match self.config.credential_lifetime_max(organization) {
+                Some(lifetime) => lifetime,
+                None => continue,
+            };
+
+            let o_auth2_clients = self.ory.list_all_clients(&organization.to_string()).await?;
+
+            for o_auth2_client in o_auth2_clients {
+                let credential = match Credential::try_from(o_auth2_client) {
+                    Ok(credential) => credential,
+                    Err(e) => {
+                        warn!("skipping malformed client of organization {organization}: {e:?}");
+                        continue;
+                    },
+                };
+
+                let mut metadata = repository::backfill(&credential);
+                metadata.expires_at = Some((metadata.created_at + lifetime).max(earliest));
+
+                repository::insert_missing(self.db.get(), metadata).await?;
+            }
+
+            let backfilled =
+                repository::backfill_expiry(self.db.get(), organization, lifetime, earliest)
+                    .await?;
+
+            if backfilled > 0 {
+                info!("set the expiry of {backfilled} credentials of organization {organization}");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn expire_credentials(&self) -> Result<()> {
+        let now = Utc::now().naive_utc();
+        let expired =
+            repository::find_expired(self.db.get(), now, self.action == ExpiryAction::Delete)
+                .await?;
+
+        for metadata in expired {
+            let client_id = metadata.client_id.clone();
+
+            if let Err(e) = self.expire(metadata).await {
+                error!("failed to deactivate expired credential {client_id}: {e:?}");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn expire(&self, metadata: credentials::Model) -> Result<()> {
+        let client_id = metadata.client_id;
+
+        let o_auth2_client = match self.ory.get_client(&client_id).await {
+            Ok(o_auth2_client) => o_auth2_client,
+            // the client was removed without its metadata, only the tombstone is missing
+            Err(OryError::ResponseError(res)) if res.status.as_u16() == 404 => {
+                return repository::tombstone(self.db.get(), &client_id, None).await;
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        let txn = self.db.get().begin().await?;
+
+        let before = repository::load_credential(&txn, o_auth2_client).await?;
+
+        let (events, action, diff) = match self.action {
+            ExpiryAction::Suspend => {
+                let after = repository::set_status(
+                    &txn,
+                    &self.ory,
+                    &before,
+                    CredentialStatus::Suspended,
+                    None,
+                )
+                .await?;
+
+                (
+                    vec![Event::Oauth2ClientSuspended(producer::payload(
+                        "",
+                        &after,
+                        Some(&before),
+                        Some(&after),
+                    ))],
+                    CredentialAuditAction::Suspended,
+                    audit::diff(Some(&before), Some(&after)),
+                )
+            },
+            ExpiryAction::Delete => {
+                // the client is only removed once the tombstone is committed, until then it is
+                // disabled so a retry after a failed commit finds it unable to get tokens
+                repository::delete(&txn, &self.ory, &before, None).await?;
+
+                (
+                    vec![
+                        Event::Oauth2ClientTokensRevoked(producer::payload(
+                            "",
+                            &before,
+                            Some(&before),
+                            Some(&before),
+                        )),
+                        Event::Oauth2ClientDeleted(producer::payload(
+                            "",
+                            &before,
+                            Some(&before),
+                            None,
+                        )),
+                    ],
+                    CredentialAuditAction::Deleted,
+                    audit::diff(Some(&before), None),
+                )
+            },
+        };
+
+        for event in events {
+            outbox::enqueue_event(&txn, event, &client_id, "").await?;
+        }
+
+        audit::record(&txn, &Actor::default(), action, &before, diff).await?;
+
+        txn.commit().await?;
+
+        info!("deactivated expired credential {client_id}");
+
+        // a client that fails to be removed stays disabled and is retried by `delete_clients`
+        if self.action == ExpiryAction::Delete {
+            repository::delete_client(&self.db, &self.ory, &client_id).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the clients of deleted credentials from Ory. Credentials are tombstoned before
+    /// their client is removed, so a removal that failed afterwards is retried here until it
+    /// succeeds.
+    async fn delete_clients(&self) -> Result<()> {
+        let deleted = repository::find_undeleted_clients(self.db.get()).await?;
+
+        for metadata in deleted {
+            let client_id = metadata.client_id;
+
+            if let Err(e) = repository::delete_client(&self.db, &self.ory, &client_id).await {
+                error!("failed to remove client of deleted credential {client_id}: {e:?}");
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn warn_owners(&self) -> Result<()> {
+        let longest = match self.warning_days.iter().max() {
+            Some(days) => *days,
+            None => return Ok(()),
+        };
+
+        let now = Utc::now().naive_utc();
+        let expiring = repository::find_expiring(
+            self.db.get(),
+            now,
+            now + ChronoDuration::days(longest.into()),
+        )
+        .await?;
+
+        for metadata in expiring {
+            let expires_at = match metadata.expires_at {
+                Some(expires_at) => expires_at,
+                None => continue,
+            };
+
+            // only the most urgent warning is sent when several lead times passed at once
+            let due = self
+                .warning_days
+                .iter()
+                .copied()
+                .filter(|days| expires_at <= now + ChronoDuration::days((*days).into()))
+                .min();
+
+            match due {
+                Some(days)
+                    if metadata
+                        .expiry_warning_days
+                        .map_or(true, |sent| sent > days) =>
+                {
+                    let client_id = metadata.client_id.clone();
+
+                    if let Err(e) = self.warn(metadata, days).await {
+                        error!("failed to warn owners of expiring credential {client_id}: {e:?}");
+                    }
+                },
+                Some(_) | None => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn warn(&self, metadata: credentials::Model, days: i32) -> Result<()> {
+        let client_id = metadata.client_id;
+        let o_auth2_client = self.ory.get_client(&client_id).await?;
+
+        let txn = self.db.get().begin().await?;
+
+        let credential = repository::load_credential(&txn, o_auth2_client).await?;
+
+        repository::record_expiry_warning(&txn, &client_id, days).await?;
+
+        let event = Event::CredentialExpiring(proto::CredentialExpiring {
+            client: Some(producer::payload(
+                "",
+                &credential,
+                Some(&credential),
+                Some(&credential),
+            )),
+            expires_at: credential
+                .expires_at
+                .map(|at| at.timestamp())
+                .unwrap_or_default(),
+            days_remaining: days,
+        });
+
+        outbox::enqueue_event(&txn, event, &client_id, "").await?;
+
+        txn.commit().await?;
+
+        info!("warned owners of credential {client_id} expiring in {days} days");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt;
+
+use async_graphql::{Context, Error, ErrorExtensions, Result};
+use hub_core::{prelude::*, uuid::Uuid};
+
+use crate::{graphql::objects::Credential, membership::Membership, AppContext};
+
+/// Builds an error carrying the `FORBIDDEN` code extension.
+#[must_use]
+pub fn forbidden(message: impl fmt::Display) -> Error {
+    Error::new(message).extend_with(|_, e| e.set("code", "FORBIDDEN"))
+}
+
+/// Builds an error carrying the `QUOTA_EXCEEDED` code extension.
+#[must_use]
+pub fn quota_exceeded(message: impl fmt::Display) -> Error {
+    Error::new(message).extend_with(|_, e| e.set("code", "QUOTA_EXCEEDED"))
+}
+
+/// Ensures the user making the request is a member of `organization`, returning the user's ID.
+///
+/// # Errors
+/// Returns an error if the `X-USER-ID` header is missing, the membership lookup fails or the user
+/// does not belong to the organization.
+pub async fn authorize_organization(ctx: &Context<'_>, organization: Uuid) -> Result<Uuid> {
+    let AppContext { user_id, .. } = ctx.data::<AppContext>()?;
+    let membership = ctx.data::<Membership>()?;
+
+    let user_id = user_id.ok_or_else(|| Error::new("X-USER-ID header not found"))?;
+
+    let is_member = membership
+        .is_member(organization, user_id)
+        .await
+        .map_err(|e| {
+            error!("failed to look up membership of organization {organization}: {e:?}");
+
+            Error::new("unable to verify organization membership")
+        })?;
+
+    if !is_member {
+        return Err(forbidden(format!(
+            "not a member of organization {organization}"
+        )));
+    }
+
+    Ok(user_id)
+}
+
+/// Ensures the user making the request is a member of the organization owning `credential`,
+/// returning the user's ID.
+///
+/// # Errors
+/// Returns an error under the same conditions as [`authorize_organization`].
+pub async fn authorize_credential(ctx: &Context<'_>, credential: &Credential) -> Result<Uuid> {
+    authorize_organization(ctx, credential.organization_id).await
+}
+
+/// Ensures `credential` belongs to `organization`.
+///
+/// # Errors
+/// Returns a `FORBIDDEN` error if the credential is owned by another organization.
+pub fn ensure_owned_by(credential: &Credential, organization: Uuid) -> Result<()> {
+    if credential.organization_id != organization {
+        return Err(forbidden(format!(
+            "credential {} does not belong to organization {organization}",
+            credential.client_id
+        )));
+    }
+
+    Ok(())
+}
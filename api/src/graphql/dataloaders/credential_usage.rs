@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use async_graphql::{dataloader::Loader as DataLoader, FieldError, Result};
+use hub_core::chrono::{Duration, Utc};
+use poem::async_trait;
+use sea_orm::prelude::*;
+
+use crate::{
+    db::Connection,
+    entities::{credential_usage, credential_usage_buckets, prelude::*},
+    graphql::objects::RequestCounts,
+};
+
+/// The number of days of daily request buckets read when counting requests.
+const REQUEST_COUNT_WINDOW_DAYS: i64 = 30;
+
+/// Loads the most recent use of credentials by client ID
+#[derive(Debug, Clone)]
+pub struct UsageLoader {
+    pub db: Connection,
+}
+
+impl UsageLoader {
+    #[must_use]
+    pub fn new(db: Connection) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl DataLoader<String> for UsageLoader {
+    type Error = FieldError;
+    type Value = credential_usage::Model;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let usage = CredentialUsage::find()
+            .filter(credential_usage::Column::ClientId.is_in(keys.iter().map(ToOwned::to_owned)))
+            .all(self.db.get())
+            .await?;
+
+        Ok(usage
+            .into_iter()
+            .map(|u| (u.client_id.clone(), u))
+            .collect())
+    }
+}
+
+/// Loads the rolling request counts of credentials by client ID
+#[derive(Debug, Clone)]
+pub struct RequestCountsLoader {
+    pub db: Connection,
+}
+
+impl RequestCountsLoader {
+    #[must_use]
+    pub fn new(db: Connection) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl DataLoader<String> for RequestCountsLoader {
+    type Error = FieldError;
+    type Value = RequestCounts;
+
+    async fn load(&self, keys: &[String]) -> Result<HashMap<String, Self::Value>, Self::Error> {
+        let today = Utc::now().date_naive();
+        let since = today - Duration::days(REQUEST_COUNT_WINDOW_DAYS - 1);
+
+        let buckets = CredentialUsageBuckets::find()
+            .filter(
+                credential_usage_buckets::Column::ClientId
+                    .is_in(keys.iter().map(ToOwned::to_owned)),
+            )
+            .filter(credential_usage_buckets::Column::Day.gte(since))
+            .all(self.db.get())
+            .await?;
+
+        let mut counts: HashMap<String, RequestCounts> = HashMap::new();
+
+        for credential_usage_buckets::Model {
+            client_id,
+            day,
+            requests,
+        } in buckets
+        {
+            let days_ago = (today - day).num_days();
+            let counts = counts.entry(client_id).or_default();
+
+            if days_ago < 1 {
+                counts.today += requests;
+            }
+
+            if days_ago < 7 {
+                counts.last_7_days += requests;
+            }
+
+            counts.last_30_days += requests;
+        }
+
+        Ok(counts)
+    }
+}
@@ -1 +1,3 @@
+mod credential_usage;
 
+pub use credential_usage::{RequestCountsLoader, UsageLoader};
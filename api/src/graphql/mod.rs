@@ -1,3 +1,4 @@
+pub mod authorization;
 pub mod dataloaders;
 pub mod mutations;
 pub mod objects;
@@ -0,0 +1,217 @@
+use async_graphql::{Context, Error, InputObject, Object, Result, SimpleObject};
+use hub_core::{
+    anyhow::Result as AnyResult,
+    chrono::{Duration, Utc},
+    prelude::*,
+};
+use ory_openapi_generated_client::models::OAuth2TokenExchange;
+use sea_orm::TransactionTrait;
+
+use crate::{
+    audit::{self, Actor},
+    backend::Backend,
+    cipher::Cipher,
+    db::Connection,
+    entities::sea_orm_active_enums::CredentialAuditAction,
+    graphql::objects::{AccessToken, Credential},
+    outbox, producer,
+    proto::credential_events::Event,
+    repository, revocation,
+    scopes::{self, Scope},
+    AppContext,
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Mutation;
+
+#[Object(name = "AccessTokenMutation")]
+impl Mutation {
+    /// Generate a new access token for an existing API credential by providing its client ID and secret.
+    pub async fn generate_access_token(
+        &self,
+        ctx: &Context<'_>,
+        input: GenerateAccessTokenInput,
+    ) -> Result<GenerateAccessTokenPayload> {
+        let app_context = ctx.data::<AppContext>()?;
+        let AppContext { db, user_id, .. } = app_context;
+        let ory = ctx.data::<Backend>()?;
+        let cipher = ctx.data::<Cipher>()?;
+
+        let requested = match input.scopes {
+            Some(scopes) if scopes.is_empty() => {
+                return Err(Error::new("at least one scope must be requested"));
+            },
+            Some(scopes) => Some(scopes::normalize(scopes)),
+            None => None,
+        };
+
+        if input.lifetime.map_or(false, |lifetime| lifetime <= 0) {
+            return Err(Error::new("lifetime must be a positive number of seconds"));
+        }
+
+        // nothing about the credential is looked up before Hydra has verified the secret, and
+        // every failure to do so is reported alike, so the API reveals nothing about clients to
+        // callers that cannot authenticate as them
+        let mut token_exchange_response = ory
+            .exchange_token(
+                input.client_id.clone(),
+                input.client_secret.clone(),
+                requested.as_deref().and_then(scopes::request).as_deref(),
+            )
+            .await
+            .map_err(|_| Error::new("invalid client credentials"))?;
+
+        let expires_in = token_exchange_response.expires_in;
+
+        // Hydra issues every token with the lifespan configured on the client, so a token requested
+        // with a shorter lifetime is revoked by the expiry scheduler once it elapses
+        let revoke_after = match (input.lifetime, token_exchange_response.expires_in) {
+            (Some(lifetime), Some(expires_in)) if lifetime < expires_in => {
+                token_exchange_response.expires_in = Some(lifetime);
+
+                Some(lifetime)
+            },
+            _ => None,
+        };
+
+        let actor = user_id.map_or_else(Actor::default, |id| app_context.actor(id));
+        let user_id = user_id.map(|id| id.to_string()).unwrap_or_default();
+
+        let recorded = match (input.lifetime, expires_in) {
+            (Some(lifetime), Some(expires_in)) if lifetime > expires_in => Err(anyhow!(
+                "lifetime cannot exceed the token lifetime of the credential, {expires_in} seconds"
+            )),
+            _ => {
+                record_issue(
+                    db,
+                    ory,
+                    cipher,
+                    &actor,
+                    &input.client_id,
+                    &user_id,
+                    &input.client_secret,
+                    &token_exchange_response,
+                    revoke_after,
+                )
+                .await
+            },
+        };
+
+        let credential = match recorded {
+            Ok(credential) => credential,
+            Err(e) => {
+                // a token that is refused, or whose shorter lifetime cannot be enforced, must not
+                // stay usable
+                if let Some(token) = &token_exchange_response.access_token {
+                    if let Err(e) = ory
+                        .revoke_token(input.client_id.clone(), input.client_secret, token)
+                        .await
+                    {
+                        error!(
+                            "failed to revoke access token of client {}: {e:?}",
+                            input.client_id
+                        );
+                    }
+                }
+
+                return Err(e.into());
+            },
+        };
+
+        let scopes = match requested {
+            Some(scopes) => scopes,
+            None => credential.scopes,
+        };
+
+        let access_token = token_exchange_response.try_into()?;
+
+        Ok(GenerateAccessTokenPayload {
+            access_token,
+            scopes,
+        })
+    }
+}
+
+/// Records that a token was issued to the credential `client_id` with an event and an audit
+/// entry and, when the token was requested with a shorter lifetime, schedules its revocation once
+/// `revoke_after` seconds have passed. All of them are committed together. Returns the
+/// credential.
+#[allow(clippy::too_many_arguments)]
+async fn record_issue(
+    db: &Connection,
+    ory: &Backend,
+    cipher: &Cipher,
+    actor: &Actor,
+    client_id: &str,
+    user_id: &str,
+    client_secret: &str,
+    token_exchange_response: &OAuth2TokenExchange,
+    revoke_after: Option<i64>,
+) -> AnyResult<Credential> {
+    let o_auth2_client = ory.get_client(client_id).await?;
+
+    let txn = db.get().begin().await?;
+
+    let credential = repository::load_credential(&txn, o_auth2_client).await?;
+
+    if let Some(lifetime) = revoke_after {
+        let token = token_exchange_response
+            .access_token
+            .clone()
+            .ok_or_else(|| anyhow!("no access token on token exchange response"))?;
+
+        revocation::schedule(
+            &txn,
+            cipher,
+            client_id,
+            client_secret.to_string(),
+            token,
+            Utc::now().naive_utc() + Duration::seconds(lifetime),
+        )
+        .await?;
+    }
+
+    let event = Event::Oauth2ClientTokenIssued(producer::payload(
+        user_id,
+        &credential,
+        Some(&credential),
+        Some(&credential),
+    ));
+
+    outbox::enqueue_event(&txn, event, &credential.client_id, user_id).await?;
+
+    audit::record(
+        &txn,
+        actor,
+        CredentialAuditAction::TokenIssued,
+        &credential,
+        audit::diff(Some(&credential), Some(&credential)),
+    )
+    .await?;
+
+    txn.commit().await?;
+
+    Ok(credential)
+}
+
+/// The input for generating a new access token from the client ID and secret of an API credential.
+#[derive(Debug, Clone, InputObject)]
+pub struct GenerateAccessTokenInput {
+    /// The unique identifier assigned to the credential.
+    pub client_id: String,
+    /// The secret of the credential.
+    pub client_secret: String,
+    /// The number of seconds the access token is valid for. It cannot exceed the token lifetime of the credential, and requesting a longer one is an error. A shorter token is revoked by the service once its lifetime elapses.
+    pub lifetime: Option<i64>,
+    /// The permissions to request for the access token. They must be granted to the credential and default to all of its scopes.
+    pub scopes: Option<Vec<Scope>>,
+}
+
+/// The response for generating a new access token.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct GenerateAccessTokenPayload {
+    /// An `AccessToken` object that can be used to authenticate requests to the Hub API.
+    access_token: AccessToken,
+    /// The permissions granted to the access token.
+    scopes: Vec<Scope>,
+}
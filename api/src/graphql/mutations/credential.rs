@@ -1,14 +1,42 @@
 use async_graphql::{Context, Error, InputObject, Object, Result, SimpleObject};
-use hub_core::{producer::Producer, uuid::Uuid};
+use hub_core::{
+    anyhow::Result as AnyResult,
+    chrono::{NaiveDateTime, TimeZone, Utc},
+    prelude::*,
+    uuid::Uuid,
+};
 use ory_openapi_generated_client::models::OAuth2Client;
+use sea_orm::{DatabaseTransaction, TransactionTrait};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    graphql::objects::{AccessToken, Credential},
-    ory_client::Client,
-    proto::{self, credential_events::Event, CredentialEventKey, CredentialEvents},
+    audit::{self, Actor},
+    backend::Backend,
+    config::CredentialArgs,
+    db::Connection,
+    entities::{
+        credentials,
+        sea_orm_active_enums::{CredentialAuditAction, CredentialStatus},
+    },
+    graphql::{
+        authorization::{authorize_credential, authorize_organization, quota_exceeded},
+        objects::{AccessToken, Credential},
+    },
+    idempotency::{Idempotency, Reservation},
+    ory_client::{add_patch, format_lifespan, CLIENT_CREDENTIALS_GRANT},
+    outbox, producer,
+    proto::{self, credential_events::Event},
+    repository,
+    scopes::{self, Scope},
     AppContext,
 };
 
+/// The operation idempotency keys of `createCredential` are recorded under.
+const CREATE_CREDENTIAL_OPERATION: &str = "createCredential";
+
+/// The operation idempotency keys of `deleteCredential` are recorded under.
+const DELETE_CREDENTIAL_OPERATION: &str = "deleteCredential";
+
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Mutation;
 
@@ -20,92 +48,350 @@ impl Mutation {
         ctx: &Context<'_>,
         input: CreateCredentialInput,
     ) -> Result<CreateCredentialPayload> {
-        let AppContext { user_id, .. } = ctx.data::<AppContext>()?;
-        let ory = ctx.data::<Client>()?;
-        let producer = ctx.data::<Producer<CredentialEvents>>()?;
-
-        let user_id = user_id.ok_or_else(|| Error::new("X-USER-ID header not found"))?;
-
-        // ory client post request payload
-        let o_auth2_client = OAuth2Client {
-            grant_types: Some(vec!["client_credentials".to_string()]),
-            client_name: Some(input.name),
-            owner: Some(input.organization.to_string()),
-            client_credentials_grant_access_token_lifespan: Some("8760h".to_string()),
-            contacts: Some(vec![user_id.to_string()]),
-            ..Default::default()
+        let app_context = ctx.data::<AppContext>()?;
+        let AppContext { db, .. } = app_context;
+        let ory = ctx.data::<Backend>()?;
+        let config = ctx.data::<CredentialArgs>()?;
+        let idempotency = ctx.data::<Idempotency>()?;
+
+        let user_id = authorize_organization(ctx, input.organization).await?;
+        let actor = app_context.actor(user_id);
+
+        let token_lifetime = match input.token_lifetime {
+            Some(token_lifetime) => {
+                validate_token_lifetime(config, input.organization, token_lifetime)?;
+
+                token_lifetime
+            },
+            None => config.default_token_lifetime(input.organization),
         };
 
-        let o_auth2_client_response = ory.create_client(&o_auth2_client).await?;
+        let expires_at = resolve_expiry(
+            config,
+            input.organization,
+            Utc::now().naive_utc(),
+            input.expires_at,
+        )?;
 
-        let client_id = o_auth2_client_response
-            .client_id
-            .clone()
-            .ok_or_else(|| Error::new("no client id on OAuth2 client response"))?;
+        let fingerprint = format!("{input:?}");
+        let idempotency_key = input.idempotency_key;
+
+        let scopes = match input.scopes {
+            Some(scopes) if scopes.is_empty() => {
+                return Err(Error::new("at least one scope must be requested"));
+            },
+            Some(scopes) => scopes::normalize(scopes),
+            None => Scope::ALL.to_vec(),
+        };
+
+        if let Some(key) = &idempotency_key {
+            let reservation = idempotency
+                .reserve(db, user_id, CREATE_CREDENTIAL_OPERATION, key, &fingerprint)
+                .await?;
+
+            if let Reservation::Completed(result) = reservation {
+                return replay_create(ory, db, result).await;
+            }
+        }
+
+        let result = match ensure_within_quota(db, ory, config, input.organization).await {
+            Ok(txn) => create(ory, txn, &actor, user_id, NewCredential {
+                organization: input.organization,
+                name: input.name,
+                description: input.description,
+                scopes,
+                token_lifetime,
+                expires_at,
+            })
+            .await
+            .map_err(Into::into),
+            Err(e) => Err(e),
+        };
+
+        if let Some(key) = &idempotency_key {
+            let remembered = result.as_ref().ok().map(CreateCredentialResult::from);
+
+            remember(
+                idempotency,
+                db,
+                user_id,
+                CREATE_CREDENTIAL_OPERATION,
+                key,
+                remembered.as_ref(),
+            )
+            .await;
+        }
+
+        result
+    }
+
+    /// Edit the name, description and token lifetime assigned to the API credential. Fields that are not edited are left untouched.
+    pub async fn edit_credential(
+        &self,
+        ctx: &Context<'_>,
+        input: EditCredentialInput,
+    ) -> Result<EditCredentialPayload> {
+        let app_context = ctx.data::<AppContext>()?;
+        let AppContext { db, .. } = app_context;
+        let ory = ctx.data::<Backend>()?;
+        let config = ctx.data::<CredentialArgs>()?;
+
+        let current_client = ory.get_client(&input.client_id).await?;
+        let current_credential =
+            repository::load_credential(db.get(), current_client.clone()).await?;
+
+        let user_id = authorize_credential(ctx, &current_credential).await?;
+
+        let mut changes = Vec::new();
+        let mut patch = Vec::new();
+
+        if input.name != current_credential.name {
+            changes.push(field_change(
+                "name",
+                Some(current_credential.name.clone()),
+                Some(input.name.clone()),
+            ));
+            patch.push(add_patch("/client_name", input.name.clone().into()));
+        }
+
+        let description = match input.description {
+            Some(description) => Some(description).filter(|d| !d.is_empty()),
+            None => current_credential.description.clone(),
+        };
+
+        if description != current_credential.description {
+            changes.push(field_change(
+                "description",
+                current_credential.description.clone(),
+                description.clone(),
+            ));
+        }
+
+        if let Some(token_lifetime) = input.token_lifetime {
+            validate_token_lifetime(config, current_credential.organization_id, token_lifetime)?;
+
+            if current_credential.token_lifetime != Some(token_lifetime) {
+                changes.push(field_change(
+                    "token_lifetime",
+                    current_credential.token_lifetime.map(|l| l.to_string()),
+                    Some(token_lifetime.to_string()),
+                ));
+                patch.push(add_patch(
+                    "/client_credentials_grant_access_token_lifespan",
+                    format_lifespan(token_lifetime).into(),
+                ));
+            }
+        }
+
+        let expires_at = match input.expires_at {
+            Some(expires_at) => resolve_expiry(
+                config,
+                current_credential.organization_id,
+                current_credential.created_at,
+                Some(expires_at),
+            )?,
+            None => current_credential.expires_at,
+        };
+
+        if expires_at != current_credential.expires_at {
+            changes.push(field_change(
+                "expires_at",
+                current_credential.expires_at.map(|at| at.to_string()),
+                expires_at.map(|at| at.to_string()),
+            ));
+        }
+
+        if changes.is_empty() {
+            return Ok(EditCredentialPayload {
+                credential: current_credential,
+            });
+        }
+
+        let txn = db.get().begin().await?;
+
+        let mut metadata = repository::find(&txn, &input.client_id)
+            .await?
+            .unwrap_or_else(|| repository::backfill(&current_credential));
+
+        if metadata.expires_at != expires_at {
+            metadata.expires_at = expires_at;
+            metadata.expiry_warning_days = None;
+        }
+
+        metadata.name = input.name;
+        metadata.description = description;
+        metadata.updated_at = Some(Utc::now().naive_utc());
+        metadata.updated_by_id = Some(user_id);
+
+        repository::save(&txn, metadata.clone()).await?;
+
+        let o_auth2_client = if patch.is_empty() {
+            current_client
+        } else {
+            ory.patch_client(&input.client_id, patch).await?
+        };
+
+        let credential = Credential::try_from(o_auth2_client)?.with_metadata(Some(metadata));
+
+        let event = Event::Oauth2ClientUpdated(proto::OAuth2ClientUpdated {
+            client: Some(producer::payload(
+                &user_id.to_string(),
+                &credential,
+                Some(&current_credential),
+                Some(&credential),
+            )),
+            changes,
+        });
+
+        outbox::enqueue_event(&txn, event, &credential.client_id, &user_id.to_string()).await?;
+
+        audit::record(
+            &txn,
+            &app_context.actor(user_id),
+            CredentialAuditAction::Updated,
+            &credential,
+            audit::diff(Some(&current_credential), Some(&credential)),
+        )
+        .await?;
+
+        txn.commit().await?;
+
+        Ok(EditCredentialPayload { credential })
+    }
+
+    /// Replace the client secret of an API credential while keeping its client ID. The previous secret stops working immediately and every access token issued with it is revoked. The new secret and a fresh access token are only returned once; if the access token cannot be exchanged the new secret is still returned.
+    pub async fn rotate_credential_secret(
+        &self,
+        ctx: &Context<'_>,
+        input: RotateCredentialSecretInput,
+    ) -> Result<RotateCredentialSecretPayload> {
+        let app_context = ctx.data::<AppContext>()?;
+        let AppContext { db, .. } = app_context;
+        let ory = ctx.data::<Backend>()?;
+
+        let current_client = ory.get_client(&input.client_id).await?;
+        let current_credential =
+            repository::load_credential(db.get(), current_client.clone()).await?;
+
+        let user_id = authorize_credential(ctx, &current_credential).await?;
+
+        let o_auth2_client_response = ory.rotate_client_secret(&input.client_id).await?;
 
         let client_secret = o_auth2_client_response
             .client_secret
             .clone()
             .ok_or_else(|| Error::new("no client_secret on OAuth2 client response"))?;
 
-        let credential: Credential = o_auth2_client_response.clone().try_into()?;
+        let credential = repository::load_credential(db.get(), o_auth2_client_response).await?;
 
-        let token_exchange_response = ory
-            .exchange_token(credential.client_id.clone(), client_secret)
-            .await?;
+        let event = Event::Oauth2ClientSecretRotated(producer::payload(
+            &user_id.to_string(),
+            &credential,
+            Some(&current_credential),
+            Some(&credential),
+        ));
 
-        let access_token = token_exchange_response.try_into()?;
+        let txn = db.get().begin().await?;
 
-        let event = CredentialEvents {
-            event: Some(Event::Oauth2ClientCreated(proto::OAuth2Client {
-                user_id: user_id.to_string(),
-                client_name: o_auth2_client_response.client_name.unwrap_or_default(),
-                organization: input.organization.to_string(),
-            })),
-        };
+        outbox::enqueue_event(&txn, event, &credential.client_id, &user_id.to_string()).await?;
 
-        let key = CredentialEventKey {
-            id: client_id.to_string(),
-            user_id: user_id.to_string(),
-        };
+        audit::record(
+            &txn,
+            &app_context.actor(user_id),
+            CredentialAuditAction::SecretRotated,
+            &credential,
+            audit::diff(Some(&current_credential), Some(&credential)),
+        )
+        .await?;
+
+        txn.commit().await?;
+
+        // tokens issued with the leaked secret must not outlive it; on failure the rotation is
+        // retried, which revokes them again
+        ory.revoke_tokens(&input.client_id).await?;
 
-        producer.send(Some(&event), Some(&key)).await?;
+        // the previous secret no longer works, so the new one is returned even without a token
+        let access_token = match exchange_access_token(ory, &credential, &client_secret).await {
+            Ok(access_token) => Some(access_token),
+            Err(e) => {
+                warn!(
+                    "failed to exchange access token of rotated credential {}: {e:?}",
+                    credential.client_id
+                );
 
-        Ok(CreateCredentialPayload {
+                None
+            },
+        };
+
+        Ok(RotateCredentialSecretPayload {
             credential,
+            client_secret,
             access_token,
         })
     }
 
-    /// Edit the name assigned to the API credential.
-    pub async fn edit_credential(
+    /// Revoke every access token issued to the API credential. The credential itself remains usable to generate new access tokens.
+    pub async fn revoke_credential_tokens(
         &self,
         ctx: &Context<'_>,
-        input: EditCredentialInput,
-    ) -> Result<EditCredentialPayload> {
-        let AppContext { user_id, .. } = ctx.data::<AppContext>()?;
-        let ory = ctx.data::<Client>()?;
-
-        let user_id = user_id.ok_or_else(|| Error::new("X-USER-ID header not found"))?;
-
-        let current_client = ory.get_client(&input.client_id.clone()).await?;
-        let current_credential: Credential = current_client.try_into()?;
-
-        // ory client post request payload
-        let o_auth2_client = OAuth2Client {
-            grant_types: Some(vec!["client_credentials".to_string()]),
-            client_name: Some(input.name),
-            owner: Some(current_credential.organization_id.clone().to_string()),
-            client_credentials_grant_access_token_lifespan: Some("8760h".to_string()),
-            contacts: Some(vec![user_id.to_string()]),
-            ..Default::default()
-        };
+        input: RevokeCredentialTokensInput,
+    ) -> Result<RevokeCredentialTokensPayload> {
+        let app_context = ctx.data::<AppContext>()?;
+        let AppContext { db, .. } = app_context;
+        let ory = ctx.data::<Backend>()?;
 
-        let o_auth2_client_response = ory.update_client(&input.client_id, &o_auth2_client).await?;
+        let current_client = ory.get_client(&input.client_id).await?;
+        let credential = repository::load_credential(db.get(), current_client).await?;
 
-        let credential: Credential = o_auth2_client_response.try_into()?;
+        let user_id = authorize_credential(ctx, &credential).await?;
 
-        Ok(EditCredentialPayload { credential })
+        ory.revoke_tokens(&input.client_id).await?;
+
+        let event = Event::Oauth2ClientTokensRevoked(producer::payload(
+            &user_id.to_string(),
+            &credential,
+            Some(&credential),
+            Some(&credential),
+        ));
+
+        let txn = db.get().begin().await?;
+
+        outbox::enqueue_event(&txn, event, &input.client_id, &user_id.to_string()).await?;
+
+        audit::record(
+            &txn,
+            &app_context.actor(user_id),
+            CredentialAuditAction::TokensRevoked,
+            &credential,
+            audit::diff(Some(&credential), Some(&credential)),
+        )
+        .await?;
+
+        txn.commit().await?;
+
+        Ok(RevokeCredentialTokensPayload { credential })
+    }
+
+    /// Suspend an API credential, for example when its secret may have leaked. A suspended credential keeps its client ID but can no longer be exchanged for access tokens, and its outstanding access tokens are revoked.
+    pub async fn suspend_credential(
+        &self,
+        ctx: &Context<'_>,
+        input: SuspendCredentialInput,
+    ) -> Result<SuspendCredentialPayload> {
+        let credential = set_status(ctx, &input.client_id, CredentialStatus::Suspended).await?;
+
+        Ok(SuspendCredentialPayload { credential })
+    }
+
+    /// Reactivate a suspended API credential so it can be exchanged for access tokens again. The client ID and secret are unchanged.
+    pub async fn reactivate_credential(
+        &self,
+        ctx: &Context<'_>,
+        input: ReactivateCredentialInput,
+    ) -> Result<ReactivateCredentialPayload> {
+        let credential = set_status(ctx, &input.client_id, CredentialStatus::Active).await?;
+
+        Ok(ReactivateCredentialPayload { credential })
     }
 
     /// Delete the OAuth2 API credential.
@@ -114,37 +400,496 @@ impl Mutation {
         ctx: &Context<'_>,
         input: DeleteCredentialInput,
     ) -> Result<DeleteCredentialPayload> {
-        let AppContext { user_id, .. } = ctx.data::<AppContext>()?;
-        let ory = ctx.data::<Client>()?;
-        let producer = ctx.data::<Producer<CredentialEvents>>()?;
-
-        let user_id = user_id.ok_or_else(|| Error::new("X-USER-ID header not found"))?;
-        let current_client = ory.get_client(&input.credential.clone()).await?;
-        let current_credential: Credential = current_client.clone().try_into()?;
-
-        ory.delete_client(&input.credential).await?;
-
-        let event = CredentialEvents {
-            event: Some(Event::Oauth2ClientDeleted(proto::OAuth2Client {
-                user_id: user_id.to_string(),
-                client_name: current_client.client_name.unwrap_or_default(),
-                organization: current_credential.organization_id.to_string(),
-            })),
-        };
+        let AppContext { db, user_id, .. } = ctx.data::<AppContext>()?;
+        let idempotency = ctx.data::<Idempotency>()?;
+
+        let fingerprint = format!("{input:?}");
 
-        let key = CredentialEventKey {
-            id: input.credential.clone(),
-            user_id: user_id.to_string(),
+        // the credential no longer exists when a delete is retried, so the key is claimed before
+        // the credential is looked up
+        let (key, user_id) = match (input.idempotency_key, user_id) {
+            (Some(key), Some(user_id)) => (key, *user_id),
+            _ => return delete(ctx, input.credential).await,
         };
 
-        producer.send(Some(&event), Some(&key)).await?;
+        let reservation = idempotency
+            .reserve(db, user_id, DELETE_CREDENTIAL_OPERATION, &key, &fingerprint)
+            .await?;
 
-        Ok(DeleteCredentialPayload {
-            credential: input.credential,
-        })
+        if let Reservation::Completed(payload) = reservation {
+            return Ok(payload);
+        }
+
+        let result = delete(ctx, input.credential).await;
+
+        remember(
+            idempotency,
+            db,
+            user_id,
+            DELETE_CREDENTIAL_OPERATION,
+            &key,
+            result.as_ref().ok(),
+        )
+        .await;
+
+        result
     }
 }
 
+/// Exchanges an access token for `credential` with its newly issued secret.
+async fn exchange_access_token(
+    ory: &Backend,
+    credential: &Credential,
+    client_secret: &str,
+) -> AnyResult<AccessToken> {
+    let token_exchange_response = ory
+        .exchange_token(
+            credential.client_id.clone(),
+            client_secret.to_string(),
+            scopes::request(&credential.scopes).as_deref(),
+        )
+        .await?;
+
+    token_exchange_response.try_into()
+}
+
+/// Revokes the tokens of a credential and deletes it, leaving a tombstone of its metadata. The
+/// client is disabled before the tombstone is committed and only removed from Hydra afterwards, so
+/// a deleted credential never stays usable and a failed removal is retried by the expiry
+/// scheduler.
+async fn delete(ctx: &Context<'_>, credential: String) -> Result<DeleteCredentialPayload> {
+    let app_context = ctx.data::<AppContext>()?;
+    let AppContext { db, .. } = app_context;
+    let ory = ctx.data::<Backend>()?;
+
+    let current_client = ory.get_client(&credential).await?;
+    let current_credential = repository::load_credential(db.get(), current_client).await?;
+
+    let user_id = authorize_credential(ctx, &current_credential).await?;
+
+    let txn = db.get().begin().await?;
+
+    repository::delete(&txn, ory, &current_credential, Some(user_id)).await?;
+
+    let actor = user_id.to_string();
+
+    for event in [
+        Event::Oauth2ClientTokensRevoked(producer::payload(
+            &actor,
+            &current_credential,
+            Some(&current_credential),
+            Some(&current_credential),
+        )),
+        Event::Oauth2ClientDeleted(producer::payload(
+            &actor,
+            &current_credential,
+            Some(&current_credential),
+            None,
+        )),
+    ] {
+        outbox::enqueue_event(&txn, event, &credential, &actor).await?;
+    }
+
+    audit::record(
+        &txn,
+        &app_context.actor(user_id),
+        CredentialAuditAction::Deleted,
+        &current_credential,
+        audit::diff(Some(&current_credential), None),
+    )
+    .await?;
+
+    txn.commit().await?;
+
+    if let Err(e) = repository::delete_client(db, ory, &credential).await {
+        warn!("failed to remove client of deleted credential {credential}: {e:?}");
+    }
+
+    Ok(DeleteCredentialPayload { credential })
+}
+
+/// Suspends or reactivates a credential. A credential already in `status` is returned unchanged.
+async fn set_status(
+    ctx: &Context<'_>,
+    client_id: &str,
+    status: CredentialStatus,
+) -> Result<Credential> {
+    let app_context = ctx.data::<AppContext>()?;
+    let AppContext { db, .. } = app_context;
+    let ory = ctx.data::<Backend>()?;
+    let config = ctx.data::<CredentialArgs>()?;
+
+    let current_client = ory.get_client(client_id).await?;
+    let current_credential = repository::load_credential(db.get(), current_client).await?;
+
+    let user_id = authorize_credential(ctx, &current_credential).await?;
+
+    if current_credential.status == status.into() {
+        return Ok(current_credential);
+    }
+
+    if status == CredentialStatus::Active
+        && current_credential
+            .expires_at
+            .map_or(false, |at| at <= Utc::now().naive_utc())
+    {
+        return Err(Error::new(
+            "credential has expired; extend its expiry before reactivating it",
+        ));
+    }
+
+    let txn = match status {
+        CredentialStatus::Active => {
+            ensure_within_quota(db, ory, config, current_credential.organization_id).await?
+        },
+        CredentialStatus::Suspended => db.get().begin().await?,
+    };
+
+    let credential =
+        repository::set_status(&txn, ory, &current_credential, status, Some(user_id)).await?;
+
+    let actor = user_id.to_string();
+    let payload = producer::payload(
+        &actor,
+        &credential,
+        Some(&current_credential),
+        Some(&credential),
+    );
+
+    let event = match status {
+        CredentialStatus::Active => Event::Oauth2ClientReactivated(payload),
+        CredentialStatus::Suspended => Event::Oauth2ClientSuspended(payload),
+    };
+
+    outbox::enqueue_event(&txn, event, client_id, &actor).await?;
+
+    let action = match status {
+        CredentialStatus::Active => CredentialAuditAction::Reactivated,
+        CredentialStatus::Suspended => CredentialAuditAction::Suspended,
+    };
+
+    audit::record(
+        &txn,
+        &app_context.actor(user_id),
+        action,
+        &credential,
+        audit::diff(Some(&current_credential), Some(&credential)),
+    )
+    .await?;
+
+    txn.commit().await?;
+
+    info!("set status of credential {client_id} to {status:?}");
+
+    Ok(credential)
+}
+
+/// Answers a retried `createCredential` from its remembered result.
+async fn replay_create(
+    ory: &Backend,
+    db: &Connection,
+    result: CreateCredentialResult,
+) -> Result<CreateCredentialPayload> {
+    let CreateCredentialResult {
+        client_id,
+        client_secret,
+        access_token,
+        expires_at,
+        expires_in,
+        token_type,
+    } = result;
+
+    let o_auth2_client = ory.get_client(&client_id).await?;
+    let credential = repository::load_credential(db.get(), o_auth2_client).await?;
+
+    let expires_at = Utc
+        .timestamp_opt(expires_at, 0)
+        .single()
+        .ok_or_else(|| Error::new("invalid remembered access token expiry"))?;
+
+    Ok(CreateCredentialPayload {
+        credential,
+        client_secret,
+        access_token: AccessToken {
+            access_token,
+            expires_at,
+            expires_in,
+            token_type,
+        },
+    })
+}
+
+/// Remembers the result of a mutation performed under an idempotency key, or releases the key when
+/// the mutation failed so it can be retried. Failures are only logged as the mutation itself has
+/// already run.
+async fn remember<T: Serialize>(
+    idempotency: &Idempotency,
+    db: &Connection,
+    user_id: Uuid,
+    operation: &str,
+    key: &str,
+    result: Option<&T>,
+) {
+    let remembered = match result {
+        Some(result) => {
+            idempotency
+                .complete(db, user_id, operation, key, result)
+                .await
+        },
+        None => idempotency.release(db, user_id, operation, key).await,
+    };
+
+    if let Err(e) = remembered {
+        error!("failed to record idempotency key {key} of {operation}: {e:?}");
+    }
+}
+
+/// A validated request to create a credential
+#[derive(Debug, Clone)]
+struct NewCredential {
+    organization: Uuid,
+    name: String,
+    description: Option<String>,
+    scopes: Vec<Scope>,
+    token_lifetime: i64,
+    expires_at: Option<NaiveDateTime>,
+}
+
+/// Creates the Ory client of a credential, then stores its metadata, exchanges its first access
+/// token and enqueues the creation event. If any step after the Ory client exists fails, the
+/// client is deleted again so the caller is never left with a credential it was not handed.
+async fn create(
+    ory: &Backend,
+    txn: DatabaseTransaction,
+    actor: &Actor,
+    user_id: Uuid,
+    new_credential: NewCredential,
+) -> AnyResult<CreateCredentialPayload> {
+    let o_auth2_client = OAuth2Client {
+        grant_types: Some(vec![CLIENT_CREDENTIALS_GRANT.to_string()]),
+        client_name: Some(new_credential.name.clone()),
+        owner: Some(new_credential.organization.to_string()),
+        client_credentials_grant_access_token_lifespan: Some(format_lifespan(
+            new_credential.token_lifetime,
+        )),
+        contacts: Some(vec![user_id.to_string()]),
+        scope: Some(scopes::join(&new_credential.scopes)),
+        ..Default::default()
+    };
+
+    let o_auth2_client_response = ory.create_client(&o_auth2_client).await?;
+
+    let client_id = o_auth2_client_response
+        .client_id
+        .clone()
+        .ok_or_else(|| anyhow!("no client id on OAuth2 client response"))?;
+
+    match complete_create(
+        ory,
+        txn,
+        actor,
+        user_id,
+        new_credential,
+        o_auth2_client_response,
+    )
+    .await
+    {
+        Ok(payload) => Ok(payload),
+        Err(e) => {
+            warn!("rolling back creation of credential {client_id}: {e:?}");
+
+            if let Err(e) = ory.delete_client(&client_id).await {
+                error!("failed to delete OAuth2 client {client_id} of failed creation: {e:?}");
+            }
+
+            Err(e)
+        },
+    }
+}
+
+/// The steps of [`create`] that follow the creation of the Ory client. The metadata and the
+/// creation event are committed together once the first access token has been exchanged.
+async fn complete_create(
+    ory: &Backend,
+    txn: DatabaseTransaction,
+    actor: &Actor,
+    user_id: Uuid,
+    new_credential: NewCredential,
+    o_auth2_client: OAuth2Client,
+) -> AnyResult<CreateCredentialPayload> {
+    let NewCredential {
+        organization,
+        name,
+        description,
+        scopes,
+        expires_at,
+        ..
+    } = new_credential;
+
+    let client_id = o_auth2_client
+        .client_id
+        .clone()
+        .ok_or_else(|| anyhow!("no client id on OAuth2 client response"))?;
+
+    let client_secret = o_auth2_client
+        .client_secret
+        .clone()
+        .ok_or_else(|| anyhow!("no client_secret on OAuth2 client response"))?;
+
+    let metadata = credentials::Model {
+        client_id: client_id.clone(),
+        organization_id: organization,
+        name,
+        description,
+        scopes: scopes.iter().map(ToString::to_string).collect(),
+        status: CredentialStatus::Active,
+        created_by_id: user_id,
+        created_at: Utc::now().naive_utc(),
+        updated_at: None,
+        updated_by_id: None,
+        deleted_by_id: None,
+        deleted_at: None,
+        expires_at,
+        expiry_warning_days: None,
+        client_deleted_at: None,
+    };
+
+    repository::save(&txn, metadata.clone()).await?;
+
+    let credential = Credential::try_from(o_auth2_client)?.with_metadata(Some(metadata));
+
+    let token_exchange_response = ory
+        .exchange_token(
+            client_id.clone(),
+            client_secret.clone(),
+            Some(&scopes::join(&scopes)),
+        )
+        .await?;
+
+    let access_token = token_exchange_response.try_into()?;
+
+    let event = Event::Oauth2ClientCreated(producer::payload(
+        &user_id.to_string(),
+        &credential,
+        None,
+        Some(&credential),
+    ));
+
+    outbox::enqueue_event(&txn, event, &client_id, &user_id.to_string()).await?;
+
+    audit::record(
+        &txn,
+        actor,
+        CredentialAuditAction::Created,
+        &credential,
+        audit::diff(None, Some(&credential)),
+    )
+    .await?;
+
+    txn.commit().await?;
+
+    Ok(CreateCredentialPayload {
+        credential,
+        client_secret,
+        access_token,
+    })
+}
+
+/// Describes the change of a single credential field for an update event. Unset values are empty.
+fn field_change(
+    field: &str,
+    old_value: Option<String>,
+    new_value: Option<String>,
+) -> proto::FieldChange {
+    proto::FieldChange {
+        field: field.to_string(),
+        old_value: old_value.unwrap_or_default(),
+        new_value: new_value.unwrap_or_default(),
+    }
+}
+
+/// Ensures `organization` has fewer active credentials than its limit, so one more can be
+/// activated. Clients created before their metadata was stored are given a metadata row first so
+/// they are counted as well. The returned transaction holds the lock of the organization, so the
+/// credential must be activated in it for the check to stay true until it commits.
+async fn ensure_within_quota(
+    db: &Connection,
+    ory: &Backend,
+    config: &CredentialArgs,
+    organization: Uuid,
+) -> Result<DatabaseTransaction> {
+    let txn = db.get().begin().await?;
+
+    repository::lock_organization(&txn, organization).await?;
+
+    let o_auth2_clients = ory.list_all_clients(&organization.to_string()).await?;
+
+    for o_auth2_client in o_auth2_clients {
+        match Credential::try_from(o_auth2_client) {
+            Ok(credential) => {
+                repository::insert_missing(&txn, repository::backfill(&credential)).await?;
+            },
+            Err(e) => warn!("skipping malformed client of organization {organization}: {e:?}"),
+        }
+    }
+
+    let limit = config.credential_limit(organization);
+    let count = repository::count_active(&txn, organization).await?;
+
+    if count >= limit {
+        return Err(quota_exceeded(format!(
+            "organization {organization} has reached its limit of {limit} active credentials"
+        )));
+    }
+
+    Ok(txn)
+}
+
+/// Checks the requested expiry of a credential created at `created_at` against the credential
+/// lifetime limit of `organization`. Credentials of organizations with a limit expire at the end
+/// of it unless an earlier expiry is requested.
+fn resolve_expiry(
+    config: &CredentialArgs,
+    organization: Uuid,
+    created_at: NaiveDateTime,
+    expires_at: Option<NaiveDateTime>,
+) -> Result<Option<NaiveDateTime>> {
+    if expires_at.map_or(false, |at| at <= Utc::now().naive_utc()) {
+        return Err(Error::new("expiry must be in the future"));
+    }
+
+    let latest = match config.credential_lifetime_max(organization) {
+        Some(max) => created_at + max,
+        None => return Ok(expires_at),
+    };
+
+    match expires_at {
+        Some(at) if at > latest => Err(Error::new(format!(
+            "credentials of this organization must expire by {latest}"
+        ))),
+        Some(at) => Ok(Some(at)),
+        None => Ok(Some(latest)),
+    }
+}
+
+fn validate_token_lifetime(
+    config: &CredentialArgs,
+    organization: Uuid,
+    token_lifetime: i64,
+) -> Result<()> {
+    let limits = config.token_lifetime_limits(organization);
+
+    if !limits.contains(&token_lifetime) {
+        return Err(Error::new(format!(
+            "token lifetime must be between {} and {} seconds",
+            limits.start(),
+            limits.end()
+        )));
+    }
+
+    Ok(())
+}
+
 /// This struct represents the input for creating a new API credential, including the ID of the organization that the credential will be associated with and the friendly name assigned to the credential.
 #[derive(InputObject, Clone, Debug)]
 pub struct CreateCredentialInput {
@@ -152,6 +897,16 @@ pub struct CreateCredentialInput {
     pub organization: Uuid,
     /// The friendly name assigned to the new API credential.
     pub name: String,
+    /// A longer explanation of what the new API credential is used for.
+    pub description: Option<String>,
+    /// The permissions to grant the new API credential. Defaults to every scope when omitted.
+    pub scopes: Option<Vec<Scope>>,
+    /// The number of seconds access tokens issued to the new API credential are valid for. Defaults to the service configured lifetime, limited to the token lifetimes allowed for the organization.
+    pub token_lifetime: Option<i64>,
+    /// The datetime in UTC after which the new API credential is deactivated. Organizations with a credential lifetime limit default to the end of it.
+    pub expires_at: Option<NaiveDateTime>,
+    /// A client generated key identifying this request. Retrying with the same key returns the original response instead of creating another credential.
+    pub idempotency_key: Option<String>,
 }
 
 /// The response payload returned after successfully creating an API credential. It includes the newly created Credential object, which represents the API credential, as well as an `AccessToken` object that can be used to authenticate requests to the Hub API.
@@ -159,23 +914,115 @@ pub struct CreateCredentialInput {
 pub struct CreateCredentialPayload {
     /// A `Credential` object representing the newly created API credential.
     credential: Credential,
+    /// The secret of the new API credential, used to generate further access tokens. It is not possible to retrieve it again.
+    client_secret: String,
     /// An `AccessToken` object that can be used to authenticate requests to the Hub API.
     access_token: AccessToken,
 }
 
-/// The input for editing the name of an existing credential by providing the `client_id` of the credential and the new `name` to be assigned.
+/// The parts of a `createCredential` response remembered to answer retries. It holds the client secret so it is only ever stored encrypted.
+#[derive(Serialize, Deserialize)]
+struct CreateCredentialResult {
+    client_id: String,
+    client_secret: String,
+    access_token: String,
+    expires_at: i64,
+    expires_in: i64,
+    token_type: String,
+}
+
+impl From<&CreateCredentialPayload> for CreateCredentialResult {
+    fn from(payload: &CreateCredentialPayload) -> Self {
+        Self {
+            client_id: payload.credential.client_id.clone(),
+            client_secret: payload.client_secret.clone(),
+            access_token: payload.access_token.access_token.clone(),
+            expires_at: payload.access_token.expires_at.timestamp(),
+            expires_in: payload.access_token.expires_in,
+            token_type: payload.access_token.token_type.clone(),
+        }
+    }
+}
+
+/// The input for editing an existing credential by providing the `client_id` of the credential and the new values to be assigned.
 #[derive(InputObject, Clone, Debug)]
 pub struct EditCredentialInput {
     /// A unique string identifier assigned to the credential during creation.
     pub client_id: String,
     /// The new name to be assigned to the credential.
     pub name: String,
+    /// The new description of the credential. The current description is kept when omitted and removed when empty.
+    pub description: Option<String>,
+    /// The number of seconds access tokens issued to the credential are valid for. The current lifetime is kept when omitted.
+    pub token_lifetime: Option<i64>,
+    /// The datetime in UTC after which the credential is deactivated. The current expiry is kept when omitted.
+    pub expires_at: Option<NaiveDateTime>,
 }
 
-/// The response for editing the name of a credential.
+/// The response for editing a credential.
 #[derive(Debug, Clone, SimpleObject)]
 pub struct EditCredentialPayload {
-    /// The updated credential with the edited name.
+    /// The updated credential with the edited fields.
+    credential: Credential,
+}
+
+/// The input for rotating the client secret of a credential.
+#[derive(Debug, Clone, InputObject)]
+pub struct RotateCredentialSecretInput {
+    /// The unique identifier assigned to the credential whose secret is rotated.
+    pub client_id: String,
+}
+
+/// The response for rotating the client secret of a credential.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct RotateCredentialSecretPayload {
+    /// The credential whose secret was rotated.
+    credential: Credential,
+    /// The new client secret. It is not possible to retrieve it again.
+    client_secret: String,
+    /// An `AccessToken` exchanged with the new client secret. It is empty when the exchange failed, in which case the new secret can be used to generate one.
+    access_token: Option<AccessToken>,
+}
+
+/// The input for revoking the access tokens of a credential.
+#[derive(Debug, Clone, InputObject)]
+pub struct RevokeCredentialTokensInput {
+    /// The unique identifier assigned to the credential whose access tokens are revoked.
+    pub client_id: String,
+}
+
+/// The response for revoking the access tokens of a credential.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct RevokeCredentialTokensPayload {
+    /// The credential whose access tokens were revoked.
+    credential: Credential,
+}
+
+/// The input for suspending a credential.
+#[derive(Debug, Clone, InputObject)]
+pub struct SuspendCredentialInput {
+    /// The unique identifier assigned to the credential to be suspended.
+    pub client_id: String,
+}
+
+/// The response for suspending a credential.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct SuspendCredentialPayload {
+    /// The suspended credential.
+    credential: Credential,
+}
+
+/// The input for reactivating a suspended credential.
+#[derive(Debug, Clone, InputObject)]
+pub struct ReactivateCredentialInput {
+    /// The unique identifier assigned to the credential to be reactivated.
+    pub client_id: String,
+}
+
+/// The response for reactivating a credential.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct ReactivateCredentialPayload {
+    /// The reactivated credential.
     credential: Credential,
 }
 
@@ -184,11 +1031,176 @@ pub struct EditCredentialPayload {
 pub struct DeleteCredentialInput {
     /// The unique identifier assigned to the credential to be deleted.
     pub credential: String,
+    /// A client generated key identifying this request. Retrying with the same key returns the original response.
+    pub idempotency_key: Option<String>,
 }
 
 /// The response for deleting a credential.
-#[derive(Debug, Clone, SimpleObject)]
+#[derive(Debug, Clone, SimpleObject, Serialize, Deserialize)]
 pub struct DeleteCredentialPayload {
     /// The unique identifier assigned to the deleted credential.
     credential: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use sea_orm::{DatabaseBackend, DbErr, MockDatabase, MockDatabaseTrait, MockExecResult};
+
+    use super::*;
+    use crate::backend::{Fault, MemoryBackend};
+
+    /// A mock database answering the metadata write, the enqueued event and the audit entry of a
+    /// creation in turn, failing the write at `fail_at` if given.
+    fn database(fail_at: Option<usize>) -> Connection {
+        let db = (0..3).fold(MockDatabase::new(DatabaseBackend::Postgres), |db, i| {
+            if fail_at == Some(i) {
+                db.append_exec_errors(vec![DbErr::Custom("database unavailable".to_string())])
+            } else {
+                db.append_exec_results(vec![MockExecResult {
+                    last_insert_id: 0,
+                    rows_affected: 1,
+                }])
+            }
+        });
+
+        db.into_connection().into()
+    }
+
+    async fn begin(db: &Connection) -> DatabaseTransaction {
+        db.get().begin().await.unwrap()
+    }
+
+    /// Renders the statements executed against a mock database.
+    fn executed(db: &Connection) -> String {
+        let log = db
+            .get()
+            .as_mock_connection()
+            .get_mocker_mutex()
+            .lock()
+            .unwrap()
+            .drain_transaction_log();
+
+        format!("{log:?}")
+    }
+
+    fn new_credential() -> NewCredential {
+        NewCredential {
+            organization: Uuid::new_v4(),
+            name: "ci".to_string(),
+            description: None,
+            scopes: vec![Scope::DropsRead],
+            token_lifetime: 3600,
+            expires_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn creates_credential() {
+        let hydra = Arc::new(MemoryBackend::default());
+        let ory: Backend = hydra.clone();
+        let db = database(None);
+        let user_id = Uuid::new_v4();
+
+        let payload = create(
+            &ory,
+            begin(&db).await,
+            &Actor::default(),
+            user_id,
+            new_credential(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(payload.credential.created_by_id, user_id);
+        assert!(ory.get_client(&payload.credential.client_id).await.is_ok());
+        assert_eq!(hydra.client_count(), 1);
+
+        let statements = executed(&db);
+
+        assert!(statements.contains("credential_event_outbox"));
+        assert!(statements.contains("credential_audit_log"));
+    }
+
+    #[tokio::test]
+    async fn nothing_to_roll_back_when_client_creation_fails() {
+        let hydra = Arc::new(MemoryBackend::default());
+        let ory: Backend = hydra.clone();
+        let db = database(None);
+
+        hydra.inject(Fault::CreateClient);
+
+        let result = create(
+            &ory,
+            begin(&db).await,
+            &Actor::default(),
+            Uuid::new_v4(),
+            new_credential(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(hydra.client_count(), 0);
+        assert!(!executed(&db).contains("credential_event_outbox"));
+    }
+
+    #[tokio::test]
+    async fn deletes_client_when_metadata_write_fails() {
+        let hydra = Arc::new(MemoryBackend::default());
+        let ory: Backend = hydra.clone();
+        let db = database(Some(0));
+
+        let result = create(
+            &ory,
+            begin(&db).await,
+            &Actor::default(),
+            Uuid::new_v4(),
+            new_credential(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(hydra.client_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn deletes_client_when_token_exchange_fails() {
+        let hydra = Arc::new(MemoryBackend::default());
+        let ory: Backend = hydra.clone();
+        let db = database(None);
+
+        hydra.inject(Fault::ExchangeToken);
+
+        let result = create(
+            &ory,
+            begin(&db).await,
+            &Actor::default(),
+            Uuid::new_v4(),
+            new_credential(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(hydra.client_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn deletes_client_when_enqueueing_event_fails() {
+        let hydra = Arc::new(MemoryBackend::default());
+        let ory: Backend = hydra.clone();
+        let db = database(Some(1));
+
+        let result = create(
+            &ory,
+            begin(&db).await,
+            &Actor::default(),
+            Uuid::new_v4(),
+            new_credential(),
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(hydra.client_count(), 0);
+    }
+}
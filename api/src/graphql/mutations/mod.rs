@@ -1,5 +1,6 @@
 #![allow(clippy::unused_async)]
+mod access_token;
 mod credential;
 
 #[derive(async_graphql::MergedObject, Default)]
-pub struct Mutation(credential::Mutation);
+pub struct Mutation(credential::Mutation, access_token::Mutation);
@@ -0,0 +1,222 @@
+use async_graphql::{
+    connection::{self, Connection, CursorType, Edge},
+    Enum, InputObject, Json, Result, SimpleObject,
+};
+use hub_core::{anyhow, chrono::NaiveDateTime, prelude::*, uuid::Uuid};
+use sea_orm::{ColumnTrait, Condition};
+use serde_json::Value;
+
+use crate::{
+    audit, db,
+    entities::{credential_audit_log, sea_orm_active_enums},
+};
+
+/// A kind of change recorded in the audit log of a credential.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialAuditAction {
+    /// The credential was created.
+    Created,
+    /// The name, description, token lifetime or expiry of the credential was edited.
+    Updated,
+    /// The client secret of the credential was rotated.
+    SecretRotated,
+    /// The access tokens of the credential were revoked.
+    TokensRevoked,
+    /// The credential was suspended.
+    Suspended,
+    /// The credential was reactivated.
+    Reactivated,
+    /// The credential was deleted.
+    Deleted,
+    /// An access token was generated for the credential.
+    TokenIssued,
+    /// An access token of the credential was revoked once the lifetime it was requested with elapsed.
+    TokenRevoked,
+}
+
+impl From<sea_orm_active_enums::CredentialAuditAction> for CredentialAuditAction {
+    fn from(value: sea_orm_active_enums::CredentialAuditAction) -> Self {
+        match value {
+            sea_orm_active_enums::CredentialAuditAction::Created => Self::Created,
+            sea_orm_active_enums::CredentialAuditAction::Updated => Self::Updated,
+            sea_orm_active_enums::CredentialAuditAction::SecretRotated => Self::SecretRotated,
+            sea_orm_active_enums::CredentialAuditAction::TokensRevoked => Self::TokensRevoked,
+            sea_orm_active_enums::CredentialAuditAction::Suspended => Self::Suspended,
+            sea_orm_active_enums::CredentialAuditAction::Reactivated => Self::Reactivated,
+            sea_orm_active_enums::CredentialAuditAction::Deleted => Self::Deleted,
+            sea_orm_active_enums::CredentialAuditAction::TokenIssued => Self::TokenIssued,
+            sea_orm_active_enums::CredentialAuditAction::TokenRevoked => Self::TokenRevoked,
+        }
+    }
+}
+
+impl From<CredentialAuditAction> for sea_orm_active_enums::CredentialAuditAction {
+    fn from(value: CredentialAuditAction) -> Self {
+        match value {
+            CredentialAuditAction::Created => Self::Created,
+            CredentialAuditAction::Updated => Self::Updated,
+            CredentialAuditAction::SecretRotated => Self::SecretRotated,
+            CredentialAuditAction::TokensRevoked => Self::TokensRevoked,
+            CredentialAuditAction::Suspended => Self::Suspended,
+            CredentialAuditAction::Reactivated => Self::Reactivated,
+            CredentialAuditAction::Deleted => Self::Deleted,
+            CredentialAuditAction::TokenIssued => Self::TokenIssued,
+            CredentialAuditAction::TokenRevoked => Self::TokenRevoked,
+        }
+    }
+}
+
+/// A change made to an API credential.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct CredentialAuditEntry {
+    /// The unique identifier of the entry.
+    pub id: i64,
+    /// The kind of change made.
+    pub action: CredentialAuditAction,
+    /// The client ID of the changed credential.
+    pub client_id: String,
+    /// The ID of the organization the credential belongs to.
+    pub organization_id: Uuid,
+    /// The ID of the user who made the change. It is empty for changes made by the service itself, such as expiring the credential.
+    pub actor_id: Option<Uuid>,
+    /// The IP address the change was requested from.
+    pub ip: Option<String>,
+    /// The user agent the change was requested with.
+    pub user_agent: Option<String>,
+    /// The fields that changed, mapping each field to its `old` and `new` value.
+    pub diff: Json<Value>,
+    /// The datetime in UTC when the change was made.
+    pub created_at: NaiveDateTime,
+}
+
+impl From<credential_audit_log::Model> for CredentialAuditEntry {
+    fn from(
+        credential_audit_log::Model {
+            id,
+            organization_id,
+            client_id,
+            action,
+            actor_id,
+            ip,
+            user_agent,
+            diff,
+            created_at,
+        }: credential_audit_log::Model,
+    ) -> Self {
+        Self {
+            id,
+            action: action.into(),
+            client_id,
+            organization_id,
+            actor_id,
+            ip,
+            user_agent,
+            diff: Json(diff),
+            created_at,
+        }
+    }
+}
+
+/// Criteria for narrowing down audit entries. Every criterion given must match.
+#[derive(Debug, Clone, Default, InputObject)]
+pub struct CredentialAuditFilter {
+    /// Only include entries recording one of these kinds of change.
+    pub actions: Option<Vec<CredentialAuditAction>>,
+    /// Only include changes made by this user.
+    pub actor_id: Option<Uuid>,
+    /// Only include changes made to the credential with this client ID.
+    pub client_id: Option<String>,
+    /// Only include changes made at or after this datetime in UTC.
+    pub created_after: Option<NaiveDateTime>,
+    /// Only include changes made before this datetime in UTC.
+    pub created_before: Option<NaiveDateTime>,
+}
+
+impl CredentialAuditFilter {
+    /// The database condition matching the entries this filter includes.
+    #[must_use]
+    pub fn condition(&self) -> Condition {
+        let Self {
+            actions,
+            actor_id,
+            client_id,
+            created_after,
+            created_before,
+        } = self.clone();
+
+        Condition::all()
+            .add_option(actions.map(|actions| {
+                credential_audit_log::Column::Action.is_in(
+                    actions
+                        .into_iter()
+                        .map(sea_orm_active_enums::CredentialAuditAction::from),
+                )
+            }))
+            .add_option(actor_id.map(|id| credential_audit_log::Column::ActorId.eq(id)))
+            .add_option(client_id.map(|id| credential_audit_log::Column::ClientId.eq(id)))
+            .add_option(created_after.map(|at| credential_audit_log::Column::CreatedAt.gte(at)))
+            .add_option(created_before.map(|at| credential_audit_log::Column::CreatedAt.lt(at)))
+    }
+}
+
+/// The position of an entry within an audit log, which is its ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuditEntryCursor(i64);
+
+impl CursorType for AuditEntryCursor {
+    type Error = anyhow::Error;
+
+    fn decode_cursor(s: &str) -> anyhow::Result<Self> {
+        Ok(Self(s.parse().map_err(|_| anyhow!("malformed cursor"))?))
+    }
+
+    fn encode_cursor(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+/// Pages through the audit entries matching `condition`, newest first.
+///
+/// # Errors
+/// Returns an error if a cursor is malformed or the database query fails.
+pub async fn audit_log(
+    db: &db::Connection,
+    condition: Condition,
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+) -> Result<Connection<AuditEntryCursor, CredentialAuditEntry>> {
+    connection::query(
+        after,
+        before,
+        first,
+        last,
+        |after: Option<AuditEntryCursor>,
+         before: Option<AuditEntryCursor>,
+         first: Option<usize>,
+         last: Option<usize>| async move {
+            let (entries, has_previous_page, has_next_page) = audit::page(
+                db.get(),
+                condition,
+                after.map(|c| c.0),
+                before.map(|c| c.0),
+                first,
+                last,
+            )
+            .await?;
+
+            let mut connection = Connection::new(has_previous_page, has_next_page);
+
+            connection.edges.extend(entries.into_iter().map(|entry| {
+                Edge::new(
+                    AuditEntryCursor(entry.id),
+                    CredentialAuditEntry::from(entry),
+                )
+            }));
+
+            Ok::<_, async_graphql::Error>(connection)
+        },
+    )
+    .await
+}
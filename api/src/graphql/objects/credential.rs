@@ -1,17 +1,71 @@
-use async_graphql::SimpleObject;
+use std::cmp::Ordering;
+
+use async_graphql::{
+    connection::Connection, ComplexObject, Context, Enum, InputObject, Result as FieldResult,
+    SimpleObject,
+};
 use hub_core::{
     anyhow::{Error, Result},
-    chrono::NaiveDateTime,
+    chrono::{Duration, NaiveDateTime, Utc},
     prelude::anyhow,
     uuid::Uuid,
 };
 use ory_openapi_generated_client::models::OAuth2Client;
+use sea_orm::ColumnTrait;
+
+use super::audit::{audit_log, AuditEntryCursor, CredentialAuditEntry, CredentialAuditFilter};
+use crate::{
+    entities::{credential_audit_log, credentials, sea_orm_active_enums},
+    ory_client::{parse_lifespan, CLIENT_CREDENTIALS_GRANT},
+    proto,
+    scopes::{self, Scope},
+    AppContext,
+};
+
+/// The state of an API credential.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialStatus {
+    /// The credential can be exchanged for access tokens.
+    Active,
+    /// The credential can no longer be exchanged for access tokens.
+    Suspended,
+}
+
+impl From<sea_orm_active_enums::CredentialStatus> for CredentialStatus {
+    fn from(value: sea_orm_active_enums::CredentialStatus) -> Self {
+        match value {
+            sea_orm_active_enums::CredentialStatus::Active => Self::Active,
+            sea_orm_active_enums::CredentialStatus::Suspended => Self::Suspended,
+        }
+    }
+}
+
+impl From<CredentialStatus> for sea_orm_active_enums::CredentialStatus {
+    fn from(value: CredentialStatus) -> Self {
+        match value {
+            CredentialStatus::Active => Self::Active,
+            CredentialStatus::Suspended => Self::Suspended,
+        }
+    }
+}
+
+impl From<CredentialStatus> for proto::CredentialStatus {
+    fn from(value: CredentialStatus) -> Self {
+        match value {
+            CredentialStatus::Active => Self::Active,
+            CredentialStatus::Suspended => Self::Suspended,
+        }
+    }
+}
 
 /// An `OAuth2` client application used for authentication with the Hub API.
 #[derive(Debug, Clone, SimpleObject)]
+#[graphql(complex)]
 pub struct Credential {
     /// A user-friendly name assigned to the credential.
     pub name: String,
+    /// A longer explanation of what the credential is used for.
+    pub description: Option<String>,
     /// A unique identifier for the credential.
     pub client_id: String,
     /// The ID of the user who created the credential.
@@ -20,6 +74,126 @@ pub struct Credential {
     pub organization_id: Uuid,
     /// The datetime in UTC when the credential was created.
     pub created_at: NaiveDateTime,
+    /// The datetime in UTC when the credential was last edited.
+    pub updated_at: Option<NaiveDateTime>,
+    /// The ID of the user who last edited the credential.
+    pub updated_by_id: Option<Uuid>,
+    /// The permissions granted to the credential. Empty for credentials created before scopes were introduced, which keep the access they were registered with.
+    pub scopes: Vec<Scope>,
+    /// The number of seconds access tokens issued to the credential are valid for.
+    pub token_lifetime: Option<i64>,
+    /// Whether the credential can currently be exchanged for access tokens.
+    pub status: CredentialStatus,
+    /// The datetime in UTC after which the credential is deactivated. Credentials without one never expire.
+    pub expires_at: Option<NaiveDateTime>,
+}
+
+#[ComplexObject]
+impl Credential {
+    /// The datetime in UTC the credential was last used to make a request to the Hub API.
+    async fn last_used_at(&self, ctx: &Context<'_>) -> FieldResult<Option<NaiveDateTime>> {
+        let AppContext { usage_loader, .. } = ctx.data::<AppContext>()?;
+
+        let usage = usage_loader.load_one(self.client_id.clone()).await?;
+
+        Ok(usage.map(|u| u.last_used_at))
+    }
+
+    /// The IP address the credential was last used from.
+    async fn last_used_ip(&self, ctx: &Context<'_>) -> FieldResult<Option<String>> {
+        let AppContext { usage_loader, .. } = ctx.data::<AppContext>()?;
+
+        let usage = usage_loader.load_one(self.client_id.clone()).await?;
+
+        Ok(usage.and_then(|u| u.last_used_ip))
+    }
+
+    /// The number of requests made to the Hub API with the credential over recent days.
+    async fn request_counts(&self, ctx: &Context<'_>) -> FieldResult<RequestCounts> {
+        let AppContext {
+            request_counts_loader,
+            ..
+        } = ctx.data::<AppContext>()?;
+
+        let counts = request_counts_loader
+            .load_one(self.client_id.clone())
+            .await?;
+
+        Ok(counts.unwrap_or_default())
+    }
+
+    /// The changes made to the credential, newest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `after` - Return entries older than this cursor.
+    /// * `before` - Return entries newer than this cursor.
+    /// * `first` - Return at most this many entries from the start of the range. Defaults to 50, up to 100.
+    /// * `last` - Return at most this many entries from the end of the range.
+    /// * `filter` - Optional criteria the entries must match.
+    async fn history(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        filter: Option<CredentialAuditFilter>,
+    ) -> FieldResult<Connection<AuditEntryCursor, CredentialAuditEntry>> {
+        let AppContext { db, .. } = ctx.data::<AppContext>()?;
+
+        let condition = filter
+            .unwrap_or_default()
+            .condition()
+            .add(credential_audit_log::Column::ClientId.eq(self.client_id.clone()));
+
+        audit_log(db, condition, after, before, first, last).await
+    }
+}
+
+impl Credential {
+    /// Overlays the metadata stored by this service onto a credential read from Ory. Ory only
+    /// remains the source of truth for the fields it enforces, such as scopes and status.
+    #[must_use]
+    pub fn with_metadata(self, metadata: Option<credentials::Model>) -> Self {
+        match metadata {
+            Some(credentials::Model {
+                description,
+                created_by_id,
+                created_at,
+                updated_at,
+                updated_by_id,
+                expires_at,
+                ..
+            }) => Self {
+                description,
+                created_by_id,
+                created_at,
+                updated_at,
+                updated_by_id,
+                expires_at,
+                ..self
+            },
+            None => self,
+        }
+    }
+
+    /// The most recent of the credential's creation and its last use, given the last use.
+    #[must_use]
+    pub fn last_active_at(&self, last_used_at: Option<NaiveDateTime>) -> NaiveDateTime {
+        last_used_at.map_or(self.created_at, |at| at.max(self.created_at))
+    }
+}
+
+/// The number of requests made with a credential, counted in whole days in UTC.
+#[derive(Debug, Clone, Copy, Default, SimpleObject)]
+pub struct RequestCounts {
+    /// Requests made today.
+    pub today: i64,
+    /// Requests made in the last 7 days, including today.
+    pub last_7_days: i64,
+    /// Requests made in the last 30 days, including today.
+    pub last_30_days: i64,
 }
 
 impl TryFrom<OAuth2Client> for Credential {
@@ -32,6 +206,9 @@ impl TryFrom<OAuth2Client> for Credential {
             contacts,
             owner,
             created_at,
+            scope,
+            client_credentials_grant_access_token_lifespan,
+            grant_types,
             ..
         }: OAuth2Client,
     ) -> Result<Self> {
@@ -49,12 +226,161 @@ impl TryFrom<OAuth2Client> for Credential {
         let created_at = created_at.ok_or_else(|| anyhow!("no created_at"))?;
         let created_at = NaiveDateTime::parse_from_str(&created_at, "%Y-%m-%dT%H:%M:%SZ")?;
 
+        // credentials created before scopes were introduced keep the scopes Hydra registered them
+        // with, none of which are in the catalogue
+        let scopes = scope.map(|s| scopes::parse(&s)).unwrap_or_default();
+
+        let token_lifetime = client_credentials_grant_access_token_lifespan
+            .as_deref()
+            .filter(|lifespan| !lifespan.is_empty())
+            .map(parse_lifespan)
+            .transpose()?;
+
+        let status = if grant_types
+            .unwrap_or_default()
+            .iter()
+            .any(|g| g == CLIENT_CREDENTIALS_GRANT)
+        {
+            CredentialStatus::Active
+        } else {
+            CredentialStatus::Suspended
+        };
+
         Ok(Self {
             name,
+            description: None,
             client_id,
             created_by_id,
             organization_id,
             created_at,
+            updated_at: None,
+            updated_by_id: None,
+            scopes,
+            token_lifetime,
+            status,
+            expires_at: None,
         })
     }
 }
+
+impl From<&Credential> for proto::CredentialSnapshot {
+    fn from(credential: &Credential) -> Self {
+        Self {
+            name: credential.name.clone(),
+            description: credential.description.clone().unwrap_or_default(),
+            scopes: credential.scopes.iter().map(ToString::to_string).collect(),
+            status: proto::CredentialStatus::from(credential.status).into(),
+            token_lifetime: credential.token_lifetime.unwrap_or_default(),
+            created_by_id: credential.created_by_id.to_string(),
+            created_at: credential.created_at.timestamp(),
+            updated_at: credential
+                .updated_at
+                .map(|at| at.timestamp())
+                .unwrap_or_default(),
+            expires_at: credential
+                .expires_at
+                .map(|at| at.timestamp())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Criteria for narrowing down the credentials of an organization. Every criterion given must match.
+#[derive(Debug, Clone, Default, InputObject)]
+pub struct CredentialFilter {
+    /// Only include credentials whose name contains this text, ignoring case.
+    pub name: Option<String>,
+    /// Only include credentials created by this user.
+    pub created_by_id: Option<Uuid>,
+    /// Only include credentials created at or after this datetime in UTC.
+    pub created_after: Option<NaiveDateTime>,
+    /// Only include credentials created before this datetime in UTC.
+    pub created_before: Option<NaiveDateTime>,
+    /// Only include credentials granted this scope.
+    pub scope: Option<Scope>,
+    /// Only include credentials in this state.
+    pub status: Option<CredentialStatus>,
+    /// Only include credentials that have not been created or used in this many days.
+    pub unused_for_days: Option<u32>,
+}
+
+impl CredentialFilter {
+    /// Checks whether `credential` satisfies every criterion of the filter other than
+    /// `unused_for_days`, which depends on recorded usage and is checked against
+    /// [`CredentialFilter::unused_since`].
+    #[must_use]
+    pub fn matches(&self, credential: &Credential) -> bool {
+        let Self {
+            name,
+            created_by_id,
+            created_after,
+            created_before,
+            scope,
+            status,
+            unused_for_days: _,
+        } = self;
+
+        name.as_ref().map_or(true, |name| {
+            credential
+                .name
+                .to_lowercase()
+                .contains(&name.to_lowercase())
+        }) && created_by_id.map_or(true, |id| credential.created_by_id == id)
+            && created_after.map_or(true, |after| credential.created_at >= after)
+            && created_before.map_or(true, |before| credential.created_at < before)
+            && scope.map_or(true, |scope| credential.scopes.contains(&scope))
+            && status.map_or(true, |status| credential.status == status)
+    }
+
+    /// The datetime in UTC credentials must not have been active since to match
+    /// `unused_for_days`.
+    #[must_use]
+    pub fn unused_since(&self) -> Option<NaiveDateTime> {
+        self.unused_for_days
+            .map(|days| Utc::now().naive_utc() - Duration::days(days.into()))
+    }
+}
+
+/// The field credentials are ordered by.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CredentialSortField {
+    /// Order by the datetime the credential was created.
+    CreatedAt,
+    /// Order by the name of the credential.
+    Name,
+}
+
+/// The direction of an ordering.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    /// Smallest value first.
+    Asc,
+    /// Largest value first.
+    Desc,
+}
+
+/// The ordering applied to the credentials of an organization.
+#[derive(Debug, Clone, Copy, InputObject)]
+pub struct CredentialSort {
+    /// The field to order credentials by.
+    pub field: CredentialSortField,
+    /// The direction of the ordering. Defaults to ascending.
+    #[graphql(default_with = "SortDirection::Asc")]
+    pub direction: SortDirection,
+}
+
+impl CredentialSort {
+    /// Compares two credentials according to the ordering.
+    #[must_use]
+    pub fn compare(&self, a: &Credential, b: &Credential) -> Ordering {
+        let ordering = match self.field {
+            CredentialSortField::CreatedAt => a.created_at.cmp(&b.created_at),
+            CredentialSortField::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+        };
+
+        match self.direction {
+            SortDirection::Asc => ordering,
+            SortDirection::Desc => ordering.reverse(),
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use async_graphql::SimpleObject;
+use hub_core::chrono::{DateTime, TimeZone, Utc};
+
+use super::Credential;
+use crate::{
+    introspection::Introspection,
+    scopes::{self, Scope},
+};
+
+/// The state of an access token and the API credential it was issued to.
+#[derive(Debug, Clone, SimpleObject)]
+pub struct TokenIntrospection {
+    /// Whether the access token may be used. Tokens that were revoked or have expired, or whose credential is suspended, expired or deleted, are inactive.
+    pub active: bool,
+    /// The permissions granted to the access token.
+    pub scopes: Vec<Scope>,
+    /// The datetime in UTC when the access token expires.
+    pub expires_at: Option<DateTime<Utc>>,
+    /// The datetime in UTC when the access token was issued.
+    pub issued_at: Option<DateTime<Utc>>,
+    /// The credential the access token was issued to. It is empty for inactive tokens.
+    pub credential: Option<Credential>,
+}
+
+impl From<Introspection> for TokenIntrospection {
+    fn from(
+        Introspection {
+            active,
+            scope,
+            expires_at,
+            issued_at,
+            credential,
+        }: Introspection,
+    ) -> Self {
+        Self {
+            active,
+            scopes: scope.as_deref().map(scopes::parse).unwrap_or_default(),
+            expires_at: expires_at.and_then(|secs| Utc.timestamp_opt(secs, 0).single()),
+            issued_at: issued_at.and_then(|secs| Utc.timestamp_opt(secs, 0).single()),
+            credential,
+        }
+    }
+}
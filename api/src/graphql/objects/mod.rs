@@ -1,7 +1,16 @@
 mod access_token;
+mod audit;
 mod credential;
+mod introspection;
 mod organization;
 
 pub use access_token::AccessToken;
-pub use credential::Credential;
+pub use audit::{
+    AuditEntryCursor, CredentialAuditAction, CredentialAuditEntry, CredentialAuditFilter,
+};
+pub use credential::{
+    Credential, CredentialFilter, CredentialSort, CredentialSortField, CredentialStatus,
+    RequestCounts, SortDirection,
+};
+pub use introspection::TokenIntrospection;
 pub use organization::Organization;
@@ -1,8 +1,25 @@
-use async_graphql::{ComplexObject, Context, Result, SimpleObject};
-use hub_core::uuid::Uuid;
+use async_graphql::{
+    connection::{self, Connection, CursorType, Edge},
+    ComplexObject, Context, Object, Result, SimpleObject,
+};
+use hub_core::{anyhow, prelude::*, uuid::Uuid};
+use sea_orm::ColumnTrait;
 
-use super::Credential;
-use crate::ory_client::Client;
+use super::{
+    audit::audit_log, AuditEntryCursor, Credential, CredentialAuditEntry, CredentialAuditFilter,
+    CredentialFilter, CredentialSort,
+};
+use crate::{
+    backend::Backend,
+    config::CredentialArgs,
+    db,
+    entities::credential_audit_log,
+    graphql::authorization::{authorize_organization, ensure_owned_by},
+    repository, usage, AppContext,
+};
+
+/// The number of clients requested from Hydra per page when walking a connection.
+const CONNECTION_PAGE_SIZE: i64 = 100;
 
 #[derive(Debug, Clone, SimpleObject)]
 #[graphql(complex)]
@@ -23,11 +40,16 @@ impl Organization {
     ///
     /// The API credential with the specified client ID.
     async fn credential(&self, ctx: &Context<'_>, client_id: String) -> Result<Credential> {
-        let ory = ctx.data::<Client>()?;
+        let AppContext { db, .. } = ctx.data::<AppContext>()?;
+        let ory = ctx.data::<Backend>()?;
+
+        authorize_organization(ctx, self.id).await?;
 
         let o_auth2_client = ory.get_client(&client_id).await?;
 
-        let credential: Credential = o_auth2_client.try_into()?;
+        let credential = repository::load_credential(db.get(), o_auth2_client).await?;
+
+        ensure_owned_by(&credential, self.id)?;
 
         Ok(credential)
     }
@@ -39,6 +61,8 @@ impl Organization {
     /// * `ctx` - The GraphQL context object containing the database connection pool and other data.
     /// * `limit` - Optional limit on the number of credentials to retrieve.
     /// * `offset` - Optional offset for the credentials to retrieve.
+    /// * `filter` - Optional criteria the credentials must match.
+    /// * `sort` - Optional ordering of the credentials. Defaults to the order they are stored in.
     ///
     /// # Returns
     ///
@@ -48,18 +72,336 @@ impl Organization {
         ctx: &Context<'_>,
         limit: Option<i64>,
         offset: Option<i64>,
+        filter: Option<CredentialFilter>,
+        sort: Option<CredentialSort>,
     ) -> Result<Vec<Credential>> {
-        let ory = ctx.data::<Client>()?;
-        let offset = offset.map(|i| i.to_string());
-        let offset = offset.as_deref();
+        let AppContext { db, .. } = ctx.data::<AppContext>()?;
+        let ory = ctx.data::<Backend>()?;
+
+        authorize_organization(ctx, self.id).await?;
+
+        let o_auth2_clients = ory.list_all_clients(&self.id.to_string()).await?;
+
+        let mut credentials = repository::load_credentials(db.get(), o_auth2_clients).await?;
+
+        if let Some(filter) = filter {
+            retain_matching(db, &filter, &mut credentials, |c| c).await?;
+        }
+
+        if let Some(sort) = sort {
+            credentials.sort_by(|a, b| sort.compare(a, b));
+        }
+
+        let offset = offset.unwrap_or_default().try_into()?;
+        let limit = limit.map_or(Ok(usize::MAX), TryInto::try_into)?;
+
+        Ok(credentials.into_iter().skip(offset).take(limit).collect())
+    }
+
+    /// The number of active API credentials this organization may have.
+    async fn credential_limit(&self, ctx: &Context<'_>) -> Result<u64> {
+        let config = ctx.data::<CredentialArgs>()?;
+
+        authorize_organization(ctx, self.id).await?;
+
+        Ok(config.credential_limit(self.id))
+    }
+
+    /// The number of active API credentials this organization has.
+    async fn credential_count(&self, ctx: &Context<'_>) -> Result<u64> {
+        let AppContext { db, .. } = ctx.data::<AppContext>()?;
+
+        authorize_organization(ctx, self.id).await?;
+
+        Ok(repository::count_active(db.get(), self.id).await?)
+    }
+
+    /// The changes made to the API credentials of this organization, newest first.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The GraphQL context object containing the database connection pool and other data.
+    /// * `after` - Return entries older than this cursor.
+    /// * `before` - Return entries newer than this cursor.
+    /// * `first` - Return at most this many entries from the start of the range. Defaults to 50, up to 100.
+    /// * `last` - Return at most this many entries from the end of the range.
+    /// * `filter` - Optional criteria the entries must match.
+    ///
+    /// # Returns
+    ///
+    /// A connection of the audit entries of this organization's credentials.
+    async fn credential_audit_log(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        filter: Option<CredentialAuditFilter>,
+    ) -> Result<Connection<AuditEntryCursor, CredentialAuditEntry>> {
+        let AppContext { db, .. } = ctx.data::<AppContext>()?;
+
+        authorize_organization(ctx, self.id).await?;
+
+        let condition = filter
+            .unwrap_or_default()
+            .condition()
+            .add(credential_audit_log::Column::OrganizationId.eq(self.id));
+
+        audit_log(db, condition, after, before, first, last).await
+    }
+
+    /// Page through the API credentials associated with this organization.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The GraphQL context object containing the database connection pool and other data.
+    /// * `after` - Return credentials after this cursor.
+    /// * `before` - Return credentials before this cursor.
+    /// * `first` - Return at most this many credentials from the start of the range.
+    /// * `last` - Return at most this many credentials from the end of the range.
+    /// * `filter` - Optional criteria the credentials must match.
+    /// * `sort` - Optional ordering of the credentials. Defaults to the order they are stored in.
+    ///
+    /// # Returns
+    ///
+    /// A connection of the API credentials associated with this organization.
+    #[allow(clippy::too_many_arguments)]
+    async fn credentials_connection(
+        &self,
+        ctx: &Context<'_>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+        filter: Option<CredentialFilter>,
+        sort: Option<CredentialSort>,
+    ) -> Result<Connection<CredentialCursor, Credential, CredentialConnectionFields>> {
+        let AppContext { db, .. } = ctx.data::<AppContext>()?;
+        let ory = ctx.data::<Backend>()?;
+
+        authorize_organization(ctx, self.id).await?;
+
+        connection::query(
+            after,
+            before,
+            first,
+            last,
+            |after: Option<CredentialCursor>,
+             before: Option<CredentialCursor>,
+             first: Option<usize>,
+             last: Option<usize>| async move {
+                let owner = self.id.to_string();
+
+                let (mut edges, has_more, total_count) = if filter.is_none() && sort.is_none() {
+                    // without a filter or ordering only the requested range is read from Hydra
+                    let limit = first.filter(|_| last.is_none());
+
+                    walk_credentials(db, ory, &owner, after.as_ref(), before.as_ref(), limit)
+                        .await?
+                } else {
+                    let (mut edges, ..) =
+                        walk_credentials(db, ory, &owner, None, None, None).await?;
 
-        let o_auth2_clients = ory
-            .list_clients(&self.id.to_string(), limit, offset)
+                    if let Some(filter) = filter {
+                        retain_matching(db, &filter, &mut edges, |(_, c)| c).await?;
+                    }
+
+                    if let Some(sort) = sort {
+                        edges.sort_by(|(_, a), (_, b)| sort.compare(a, b));
+                    }
+
+                    let total_count = edges.len().try_into().ok();
+                    let position = |cursor: &CredentialCursor| {
+                        edges
+                            .iter()
+                            .position(|(c, _)| c == cursor)
+                            .ok_or_else(|| async_graphql::Error::new("invalid cursor"))
+                    };
+
+                    let end = before.as_ref().map_or(Ok(edges.len()), position)?;
+                    let start = after
+                        .as_ref()
+                        .map_or(Ok(0), |c| position(c).map(|i| i + 1))?;
+
+                    edges.truncate(end);
+                    edges.drain(..start.min(end));
+
+                    (edges, false, total_count)
+                };
+
+                let mut has_previous_page = after.is_some();
+                let mut has_next_page = has_more || before.is_some();
+
+                if let Some(first) = first {
+                    if edges.len() > first {
+                        edges.truncate(first);
+                        has_next_page = true;
+                    }
+                }
+
+                if let Some(last) = last {
+                    if edges.len() > last {
+                        edges.drain(..edges.len() - last);
+                        has_previous_page = true;
+                    }
+                }
+
+                let mut connection = Connection::with_additional_fields(
+                    has_previous_page,
+                    has_next_page,
+                    CredentialConnectionFields {
+                        organization: self.id,
+                        total_count,
+                    },
+                );
+
+                connection.edges.extend(
+                    edges
+                        .into_iter()
+                        .map(|(cursor, credential)| Edge::new(cursor, credential)),
+                );
+
+                Ok::<_, async_graphql::Error>(connection)
+            },
+        )
+        .await
+    }
+}
+
+/// Drops the items whose credential does not match `filter`, looking up when the credentials were
+/// last used if the filter asks for unused ones.
+async fn retain_matching<T>(
+    db: &db::Connection,
+    filter: &CredentialFilter,
+    items: &mut Vec<T>,
+    credential: impl Fn(&T) -> &Credential,
+) -> Result<()> {
+    items.retain(|i| filter.matches(credential(i)));
+
+    if let Some(since) = filter.unused_since() {
+        let client_ids = items
+            .iter()
+            .map(|i| credential(i).client_id.clone())
+            .collect();
+        let last_used_at = usage::last_used_at(db, client_ids).await?;
+
+        items.retain(|i| {
+            let credential = credential(i);
+
+            credential.last_active_at(last_used_at.get(&credential.client_id).copied()) < since
+        });
+    }
+
+    Ok(())
+}
+
+/// The credentials collected while walking an organization's listing, whether more follow and the
+/// total count reported by Hydra.
+type Walk = (Vec<(CredentialCursor, Credential)>, bool, Option<i64>);
+
+/// Walks the credentials owned by `owner` in the order Hydra stores them, starting just after
+/// `after` and stopping at `before` or once `limit` credentials have been collected.
+async fn walk_credentials(
+    db: &db::Connection,
+    ory: &Backend,
+    owner: &str,
+    after: Option<&CredentialCursor>,
+    before: Option<&CredentialCursor>,
+    limit: Option<usize>,
+) -> Result<Walk> {
+    let mut page_token = after.and_then(|c| c.page_token.clone());
+    let mut skip = after.map_or(0, |c| c.index + 1);
+    let mut edges = Vec::new();
+    let mut total_count = None;
+
+    loop {
+        let page = ory
+            .list_clients_page(owner, CONNECTION_PAGE_SIZE, page_token.as_deref())
             .await?;
 
-        o_auth2_clients
-            .into_iter()
-            .map(|c| c.try_into().map_err(Into::into))
-            .collect::<_>()
+        total_count = total_count.or(page.total_count);
+
+        let clients = page.clients.into_iter().skip(skip).collect();
+        let credentials = repository::load_credentials(db.get(), clients).await?;
+
+        for (index, credential) in credentials.into_iter().enumerate() {
+            let index = index + skip;
+            let cursor = CredentialCursor {
+                page_token: page_token.clone(),
+                index,
+            };
+
+            if before == Some(&cursor) {
+                return Ok((edges, false, total_count));
+            }
+
+            if limit.map_or(false, |limit| edges.len() == limit) {
+                return Ok((edges, true, total_count));
+            }
+
+            edges.push((cursor, credential));
+        }
+
+        skip = 0;
+
+        match page.next_page_token {
+            Some(next) if !next.is_empty() => page_token = Some(next),
+            _ => return Ok((edges, false, total_count)),
+        }
+    }
+}
+
+/// The position of a credential within the listing of its organization. Hydra only hands out
+/// opaque tokens for the start of each page so the cursor pairs the token of the page holding the
+/// credential with its index on that page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CredentialCursor {
+    page_token: Option<String>,
+    index: usize,
+}
+
+impl CursorType for CredentialCursor {
+    type Error = anyhow::Error;
+
+    fn decode_cursor(s: &str) -> anyhow::Result<Self> {
+        let (index, page_token) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("malformed cursor"))?;
+
+        Ok(Self {
+            page_token: (!page_token.is_empty()).then(|| page_token.to_string()),
+            index: index.parse()?,
+        })
+    }
+
+    fn encode_cursor(&self) -> String {
+        format!(
+            "{}:{}",
+            self.index,
+            self.page_token.as_deref().unwrap_or_default()
+        )
+    }
+}
+
+/// Additional fields of the connection of an organization's credentials.
+#[derive(Debug, Clone)]
+pub struct CredentialConnectionFields {
+    organization: Uuid,
+    total_count: Option<i64>,
+}
+
+#[Object]
+impl CredentialConnectionFields {
+    /// The total number of credentials associated with the organization.
+    async fn total_count(&self, ctx: &Context<'_>) -> Result<i64> {
+        if let Some(total_count) = self.total_count {
+            return Ok(total_count);
+        }
+
+        let ory = ctx.data::<Backend>()?;
+        let o_auth2_clients = ory.list_all_clients(&self.organization.to_string()).await?;
+
+        Ok(o_auth2_clients.len().try_into()?)
     }
 }
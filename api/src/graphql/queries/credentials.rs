@@ -1,6 +1,10 @@
 use async_graphql::{Context, Object, Result};
 
-use crate::{graphql::objects::Credential, ory_client::Client};
+use crate::{
+    backend::Backend,
+    graphql::{authorization::authorize_credential, objects::Credential},
+    repository, AppContext,
+};
 
 #[derive(Default)]
 pub struct Query;
@@ -13,10 +17,13 @@ impl Query {
         ctx: &Context<'_>,
         #[graphql(key)] client_id: String,
     ) -> Result<Credential> {
-        let ory = ctx.data::<Client>()?;
+        let AppContext { db, .. } = ctx.data::<AppContext>()?;
+        let ory = ctx.data::<Backend>()?;
 
         let o_auth2_client = ory.get_client(&client_id).await?;
-        let credential: Credential = o_auth2_client.try_into()?;
+        let credential = repository::load_credential(db.get(), o_auth2_client).await?;
+
+        authorize_credential(ctx, &credential).await?;
 
         Ok(credential)
     }
@@ -0,0 +1,35 @@
+use async_graphql::{Context, Error, Object, Result};
+
+use crate::{
+    backend::Backend,
+    graphql::{authorization::authorize_credential, objects::TokenIntrospection},
+    introspection::{self, IntrospectionCache},
+    AppContext,
+};
+
+#[derive(Default)]
+pub struct Query;
+
+#[Object(name = "IntrospectionQuery")]
+impl Query {
+    /// Looks up whether an access token may be used and the API credential it was issued to. Only members of the organization owning the credential may introspect its tokens.
+    async fn introspect_token(
+        &self,
+        ctx: &Context<'_>,
+        token: String,
+    ) -> Result<TokenIntrospection> {
+        let AppContext { db, user_id, .. } = ctx.data::<AppContext>()?;
+        let ory = ctx.data::<Backend>()?;
+        let cache = ctx.data::<IntrospectionCache>()?;
+
+        user_id.ok_or_else(|| Error::new("X-USER-ID header not found"))?;
+
+        let introspection = introspection::introspect(db, ory, cache, &token).await?;
+
+        if let Some(credential) = &introspection.credential {
+            authorize_credential(ctx, credential).await?;
+        }
+
+        Ok(introspection.into())
+    }
+}
@@ -1,9 +1,14 @@
 #![allow(clippy::unused_async)]
 
 mod credentials;
+mod introspection;
 mod organizations;
 
 // Add your other ones here to create a unified Query object
 // e.x. Query(SomeQuery, OtherQuery, OtherOtherQuery)
 #[derive(async_graphql::MergedObject, Default)]
-pub struct Query(credentials::Query, organizations::Query);
+pub struct Query(
+    credentials::Query,
+    introspection::Query,
+    organizations::Query,
+);
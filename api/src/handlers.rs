@@ -2,11 +2,18 @@ use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
 use async_graphql_poem::{GraphQLRequest, GraphQLResponse};
 use poem::{
     handler,
-    web::{Data, Html},
-    IntoResponse, Result,
+    http::StatusCode,
+    web::{Data, Form, Html, Json},
+    IntoResponse, Response, Result,
 };
+use serde_json::json;
 
-use crate::{AppContext, AppState, UserID};
+use crate::{
+    introspection::{self, IntrospectionRequest, IntrospectionResponse},
+    token_hook::{self, Decision, TokenHookRequest},
+    usage::{self, UsageReport},
+    AppContext, AppState, RequestInfo, UserID,
+};
 
 #[handler]
 pub fn health() {}
@@ -20,12 +27,13 @@ pub fn playground() -> impl IntoResponse {
 pub async fn graphql_handler(
     Data(state): Data<&AppState>,
     user_id: UserID,
+    request: RequestInfo,
     req: GraphQLRequest,
 ) -> Result<GraphQLResponse> {
     let ory = &state.ory;
     let UserID(user_id) = user_id;
 
-    let context = AppContext::new(user_id);
+    let context = AppContext::new(state.connection.clone(), user_id, request);
 
     Ok(state
         .schema
@@ -33,8 +41,59 @@ pub async fn graphql_handler(
             req.0
                 .data(context)
                 .data(ory.clone())
-                .data(state.producer.clone()),
+                .data(state.credentials.clone())
+                .data(state.membership.clone())
+                .data(state.idempotency.clone())
+                .data(state.cipher.clone())
+                .data(state.introspection.clone()),
         )
         .await
         .into())
 }
+
+/// Records a batch of credential usage reports sent by the API gateway.
+#[handler]
+pub async fn usage_handler(
+    Data(state): Data<&AppState>,
+    Json(reports): Json<Vec<UsageReport>>,
+) -> Result<()> {
+    usage::record(&state.connection, reports).await?;
+
+    Ok(())
+}
+
+/// Implements the Hydra token hook, adding the organization and credential of the client to the
+/// claims of client credentials access tokens and denying tokens to suspended or expired
+/// credentials.
+#[handler]
+pub async fn token_hook_handler(
+    Data(state): Data<&AppState>,
+    Json(TokenHookRequest { request }): Json<TokenHookRequest>,
+) -> Result<Response> {
+    let response = match token_hook::decide(&state.connection, &state.ory, request).await? {
+        Decision::Unchanged => StatusCode::NO_CONTENT.into_response(),
+        Decision::Allow(claims) => Json(claims).into_response(),
+        Decision::Deny(reason) => Json(json!({
+            "error": "access_denied",
+            "error_description": reason,
+        }))
+        .with_status(StatusCode::FORBIDDEN)
+        .into_response(),
+    };
+
+    Ok(response)
+}
+
+/// Introspects an access token for the API gateway, answering whether it may be used and which
+/// organization and credential it belongs to.
+#[handler]
+pub async fn introspection_handler(
+    Data(state): Data<&AppState>,
+    Form(IntrospectionRequest { token }): Form<IntrospectionRequest>,
+) -> Result<Json<IntrospectionResponse>> {
+    let introspection =
+        introspection::introspect(&state.connection, &state.ory, &state.introspection, &token)
+            .await?;
+
+    Ok(Json(introspection.into()))
+}
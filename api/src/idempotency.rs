@@ -0,0 +1,207 @@
+use hub_core::{
+    anyhow::Result,
+    chrono::{Duration, Utc},
+    clap,
+    prelude::*,
+    uuid::Uuid,
+};
+use sea_orm::{prelude::*, ActiveValue::Set, Condition, DbBackend, Statement};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    cipher::Cipher,
+    db::Connection,
+    entities::{idempotency_keys, prelude::*},
+};
+
+/// Claims an idempotency key for a new request. An existing claim is only taken over once it has
+/// expired, or once a request that never completed has held it for longer than its lease, in
+/// which case the row is returned just like a fresh insert.
+const RESERVE: &str = r"
+INSERT INTO idempotency_keys (user_id, operation, key, fingerprint, created_at, expires_at)
+VALUES ($1, $2, $3, $4, $5, $6)
+ON CONFLICT (user_id, operation, key) DO UPDATE
+SET fingerprint = EXCLUDED.fingerprint,
+    response = NULL,
+    created_at = EXCLUDED.created_at,
+    expires_at = EXCLUDED.expires_at
+WHERE idempotency_keys.expires_at < EXCLUDED.created_at
+OR (idempotency_keys.response IS NULL AND idempotency_keys.created_at < $7)
+RETURNING key
+";
+
+/// Arguments for remembering the results of mutations retried with an idempotency key
+#[derive(Debug, Clone, clap::Args)]
+pub struct IdempotencyArgs {
+    /// The number of seconds the result of a mutation is remembered for its idempotency key.
+    #[arg(long, env, default_value_t = 86_400)]
+    pub idempotency_key_ttl: i64,
+
+    /// The number of seconds a request holds its idempotency key before a retry may take it over,
+    /// should the request never complete.
+    #[arg(long, env, default_value_t = 60)]
+    pub idempotency_reservation_lease: i64,
+
+    /// The hex encoded 256-bit key used to encrypt remembered mutation results.
+    #[arg(long, env)]
+    pub idempotency_encryption_key: String,
+}
+
+/// The state of an idempotency key after attempting to claim it
+#[derive(Debug)]
+pub enum Reservation<T> {
+    /// The key was claimed and the mutation should be performed.
+    Reserved,
+    /// The mutation already completed under the key with this result.
+    Completed(T),
+}
+
+/// Remembers the encrypted results of mutations by idempotency key so retries can be answered
+/// without performing the mutation twice
+#[derive(Clone)]
+pub struct Idempotency {
+    cipher: Cipher,
+    ttl: Duration,
+    lease: Duration,
+}
+
+impl Idempotency {
+    /// Builds the store from its configuration.
+    ///
+    /// # Errors
+    /// Returns an error if the encryption key is not 32 hex encoded bytes.
+    pub fn new(args: IdempotencyArgs) -> Result<Self> {
+        let IdempotencyArgs {
+            idempotency_key_ttl,
+            idempotency_reservation_lease,
+            idempotency_encryption_key,
+        } = args;
+
+        Ok(Self {
+            cipher: Cipher::new(&idempotency_encryption_key)?,
+            ttl: Duration::seconds(idempotency_key_ttl),
+            lease: Duration::seconds(idempotency_reservation_lease),
+        })
+    }
+
+    /// Claims `key` for `operation` on behalf of `user_id`. If the operation already completed
+    /// under the key, its remembered result is returned instead. A key still held by a request
+    /// that started more than the reservation lease ago is taken over, as that request is assumed
+    /// to have crashed.
+    ///
+    /// # Errors
+    /// Returns an error if the key was used for a request with a different `fingerprint`, a
+    /// request with the key is still in progress or the database query fails.
+    pub async fn reserve<T: DeserializeOwned>(
+        &self,
+        db: &Connection,
+        user_id: Uuid,
+        operation: &str,
+        key: &str,
+        fingerprint: &str,
+    ) -> Result<Reservation<T>> {
+        let now = Utc::now().naive_utc();
+
+        let reserved = db
+            .get()
+            .query_one(Statement::from_sql_and_values(
+                DbBackend::Postgres,
+                RESERVE,
+                [
+                    user_id.into(),
+                    operation.into(),
+                    key.into(),
+                    fingerprint.into(),
+                    now.into(),
+                    (now + self.ttl).into(),
+                    (now - self.lease).into(),
+                ],
+            ))
+            .await?;
+
+        if reserved.is_some() {
+            return Ok(Reservation::Reserved);
+        }
+
+        let existing =
+            IdempotencyKeys::find_by_id((user_id, operation.to_string(), key.to_string()))
+                .one(db.get())
+                .await?
+                .ok_or_else(|| {
+                    anyhow!("a request with this idempotency key is still in progress")
+                })?;
+
+        if existing.fingerprint != fingerprint {
+            return Err(anyhow!(
+                "idempotency key was already used for a different request"
+            ));
+        }
+
+        let response = existing
+            .response
+            .ok_or_else(|| anyhow!("a request with this idempotency key is still in progress"))?;
+
+        let result = serde_json::from_slice(&self.cipher.decrypt(&response)?)?;
+
+        Ok(Reservation::Completed(result))
+    }
+
+    /// Remembers the result of the operation performed under a claimed key.
+    ///
+    /// # Errors
+    /// Returns an error if the result cannot be encrypted or the database write fails.
+    pub async fn complete<T: Serialize>(
+        &self,
+        db: &Connection,
+        user_id: Uuid,
+        operation: &str,
+        key: &str,
+        result: &T,
+    ) -> Result<()> {
+        let response = self.cipher.encrypt(&serde_json::to_vec(result)?)?;
+
+        let active_model = idempotency_keys::ActiveModel {
+            response: Set(Some(response)),
+            ..Default::default()
+        };
+
+        IdempotencyKeys::update_many()
+            .set(active_model)
+            .filter(idempotency_keys::Column::UserId.eq(user_id))
+            .filter(idempotency_keys::Column::Operation.eq(operation))
+            .filter(idempotency_keys::Column::Key.eq(key))
+            .exec(db.get())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Gives up a claimed key after the operation failed so it can be retried. Expired keys of
+    /// every user are removed at the same time.
+    ///
+    /// # Errors
+    /// Returns an error if the database write fails.
+    pub async fn release(
+        &self,
+        db: &Connection,
+        user_id: Uuid,
+        operation: &str,
+        key: &str,
+    ) -> Result<()> {
+        IdempotencyKeys::delete_many()
+            .filter(
+                Condition::any()
+                    .add(
+                        Condition::all()
+                            .add(idempotency_keys::Column::UserId.eq(user_id))
+                            .add(idempotency_keys::Column::Operation.eq(operation))
+                            .add(idempotency_keys::Column::Key.eq(key)),
+                    )
+                    .add(idempotency_keys::Column::ExpiresAt.lt(Utc::now().naive_utc())),
+            )
+            .exec(db.get())
+            .await?;
+
+        Ok(())
+    }
+}
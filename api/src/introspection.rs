@@ -0,0 +1,305 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use hub_core::{anyhow::Result, chrono::Utc, clap, prelude::*, uuid::Uuid};
+use ory_openapi_generated_client::{apis::Error as OryError, models::IntrospectedOAuth2Token};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    audit,
+    backend::Backend,
+    db::Connection,
+    graphql::objects::{Credential, CredentialStatus},
+    repository,
+    scopes::Scope,
+};
+
+/// Arguments for introspecting access tokens on behalf of the API gateway
+#[derive(Debug, Clone, clap::Args)]
+pub struct IntrospectionArgs {
+    /// The number of seconds an active introspection result is reused for before Hydra is asked
+    /// again.
+    #[arg(long, env, default_value_t = 30)]
+    pub introspection_cache_ttl: u64,
+}
+
+/// The state of an access token and the credential it was issued to.
+#[derive(Debug, Clone)]
+pub struct Introspection {
+    /// Whether the token is valid and its credential is active and unexpired.
+    pub active: bool,
+    /// The scopes granted to the token, space separated.
+    pub scope: Option<String>,
+    /// The unix timestamp the token expires at.
+    pub expires_at: Option<i64>,
+    /// The unix timestamp the token was issued at.
+    pub issued_at: Option<i64>,
+    /// The credential the token was issued to.
+    pub credential: Option<Credential>,
+}
+
+impl Introspection {
+    fn inactive() -> Self {
+        Self {
+            active: false,
+            scope: None,
+            expires_at: None,
+            issued_at: None,
+            credential: None,
+        }
+    }
+}
+
+/// The form posted to the introspection endpoint, following RFC 7662
+#[derive(Debug, Clone, Deserialize)]
+pub struct IntrospectionRequest {
+    pub token: String,
+}
+
+/// The body returned by the introspection endpoint, following RFC 7662 with the organization,
+/// status and scopes of the credential added
+#[derive(Debug, Clone, Serialize)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub organization_id: Option<Uuid>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_status: Option<&'static str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub credential_scopes: Vec<&'static str>,
+}
+
+impl From<Introspection> for IntrospectionResponse {
+    fn from(
+        Introspection {
+            active,
+            scope,
+            expires_at,
+            issued_at,
+            credential,
+        }: Introspection,
+    ) -> Self {
+        let status = credential.as_ref().map(|c| match c.status {
+            CredentialStatus::Active => "active",
+            CredentialStatus::Suspended => "suspended",
+        });
+
+        Self {
+            active,
+            client_id: credential.as_ref().map(|c| c.client_id.clone()),
+            scope,
+            exp: expires_at,
+            iat: issued_at,
+            organization_id: credential.as_ref().map(|c| c.organization_id),
+            credential_status: status,
+            credential_scopes: credential
+                .map(|c| c.scopes.into_iter().map(Scope::as_str).collect())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The SHA-256 digest of an access token, so the cache never holds usable tokens
+type TokenHash = [u8; 32];
+
+#[derive(Debug)]
+struct Entry {
+    introspection: Introspection,
+    /// The audit log watermark read before the token was introspected.
+    watermark: i64,
+    cached_until: Instant,
+}
+
+#[derive(Debug)]
+struct Entries {
+    entries: HashMap<TokenHash, Entry>,
+    next_purge: Instant,
+}
+
+/// Remembers active introspection results for a short time. Entries are dropped when an event is
+/// received for their credential, but the consumer group delivers each event to a single replica,
+/// so every hit is also checked against the audit log shared by all replicas before it is used.
+#[derive(Debug, Clone)]
+pub struct IntrospectionCache {
+    ttl: Duration,
+    entries: Arc<Mutex<Entries>>,
+}
+
+impl IntrospectionCache {
+    #[must_use]
+    pub fn new(args: IntrospectionArgs) -> Self {
+        let IntrospectionArgs {
+            introspection_cache_ttl,
+        } = args;
+
+        let ttl = Duration::from_secs(introspection_cache_ttl);
+
+        Self {
+            ttl,
+            entries: Arc::new(Mutex::new(Entries {
+                entries: HashMap::new(),
+                next_purge: Instant::now() + ttl,
+            })),
+        }
+    }
+
+    fn hash(token: &str) -> TokenHash {
+        Sha256::digest(token.as_bytes()).into()
+    }
+
+    /// The unexpired result cached for `token` and the audit log watermark it was read at.
+    fn get(&self, token: &TokenHash) -> Option<(Introspection, i64)> {
+        let mut entries = self.entries.lock().ok()?;
+        let entry = entries.entries.get(token)?;
+
+        if entry.cached_until <= Instant::now() {
+            entries.entries.remove(token);
+
+            return None;
+        }
+
+        Some((entry.introspection.clone(), entry.watermark))
+    }
+
+    fn insert(&self, token: TokenHash, introspection: Introspection, watermark: i64) {
+        // never reuse the result past the expiry of the token itself
+        let remaining = introspection
+            .expires_at
+            .map(|exp| exp - Utc::now().timestamp())
+            .and_then(|secs| u64::try_from(secs).ok())
+            .map_or(self.ttl, |secs| self.ttl.min(Duration::from_secs(secs)));
+
+        if let Ok(mut entries) = self.entries.lock() {
+            let now = Instant::now();
+
+            // entries of tokens that are never presented again are swept at most once per TTL
+            if entries.next_purge <= now {
+                entries.entries.retain(|_, entry| entry.cached_until > now);
+                entries.next_purge = now + self.ttl;
+            }
+
+            entries.entries.insert(token, Entry {
+                introspection,
+                watermark,
+                cached_until: now + remaining,
+            });
+        }
+    }
+
+    fn remove(&self, token: &TokenHash) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.entries.remove(token);
+        }
+    }
+
+    /// Drops the cached results of tokens issued to `client_id`.
+    pub fn invalidate(&self, client_id: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.entries.retain(|_, entry| {
+                entry
+                    .introspection
+                    .credential
+                    .as_ref()
+                    .map_or(true, |c| c.client_id != client_id)
+            });
+        }
+    }
+}
+
+/// Introspects `token` with Hydra and looks up the credential it was issued to. Tokens of
+/// credentials that are suspended, expired or deleted are reported inactive.
+///
+/// # Errors
+/// Returns an error if the Ory requests or database queries fail or the Ory client is malformed.
+pub async fn introspect(
+    db: &Connection,
+    ory: &Backend,
+    cache: &IntrospectionCache,
+    token: &str,
+) -> Result<Introspection> {
+    let key = IntrospectionCache::hash(token);
+
+    if let Some((introspection, watermark)) = cache.get(&key) {
+        if is_current(db, &introspection, watermark).await? {
+            return Ok(introspection);
+        }
+
+        cache.remove(&key);
+    }
+
+    // read before Hydra is asked, so a change racing the introspection lands past the watermark
+    let watermark = audit::watermark(db.get()).await?;
+
+    let IntrospectedOAuth2Token {
+        active,
+        client_id,
+        scope,
+        exp,
+        iat,
+        ..
+    } = ory.introspect_token(token).await?;
+
+    let client_id = match client_id {
+        Some(client_id) if active => client_id,
+        _ => return Ok(Introspection::inactive()),
+    };
+
+    let o_auth2_client = match ory.get_client(&client_id).await {
+        Ok(o_auth2_client) => o_auth2_client,
+        Err(OryError::ResponseError(res)) if res.status.as_u16() == 404 => {
+            return Ok(Introspection::inactive());
+        },
+        Err(e) => return Err(e.into()),
+    };
+
+    let credential = repository::load_credential(db.get(), o_auth2_client).await?;
+
+    if credential.status == CredentialStatus::Suspended || expired(&credential) {
+        return Ok(Introspection::inactive());
+    }
+
+    let introspection = Introspection {
+        active: true,
+        scope,
+        expires_at: exp,
+        issued_at: iat,
+        credential: Some(credential),
+    };
+
+    cache.insert(key, introspection.clone(), watermark);
+
+    Ok(introspection)
+}
+
+/// Whether a cached result still holds: its credential has not expired since and no change to it
+/// has been logged past `watermark`.
+async fn is_current(
+    db: &Connection,
+    introspection: &Introspection,
+    watermark: i64,
+) -> Result<bool> {
+    let credential = match &introspection.credential {
+        Some(credential) if !expired(credential) => credential,
+        Some(_) | None => return Ok(false),
+    };
+
+    Ok(!audit::changed_since(db.get(), &credential.client_id, watermark).await?)
+}
+
+fn expired(credential: &Credential) -> bool {
+    credential
+        .expires_at
+        .map_or(false, |at| at <= Utc::now().naive_utc())
+}
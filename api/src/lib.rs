@@ -2,37 +2,123 @@
 #![warn(clippy::pedantic, clippy::cargo)]
 #![allow(clippy::module_name_repetitions)]
 
+pub mod audit;
+pub mod auth;
+pub mod backend;
+pub mod cipher;
+pub mod config;
+pub mod db;
+pub mod entities;
+pub mod events;
+pub mod expiry;
 pub mod graphql;
 pub mod handlers;
+pub mod idempotency;
+pub mod introspection;
+pub mod membership;
 pub mod ory_client;
+pub mod outbox;
+pub mod producer;
+pub mod repository;
+pub mod revocation;
+pub mod scopes;
+pub mod token_hook;
+pub mod usage;
 
+use async_graphql::dataloader::DataLoader;
+use db::Connection;
+use graphql::dataloaders::{RequestCountsLoader, UsageLoader};
 use hub_core::{
     anyhow::{Error, Result},
     clap,
+    consumer::RecvError,
     prelude::*,
-    producer::Producer,
+    tokio,
     uuid::Uuid,
 };
 use poem::{async_trait, FromRequest, Request, RequestBody};
+use prost::Message;
 
 pub mod proto {
     include!(concat!(env!("OUT_DIR"), "/credential.proto.rs"));
+    include!(concat!(env!("OUT_DIR"), "/organization.proto.rs"));
 }
 
-use proto::CredentialEvents;
-
 impl hub_core::producer::Message for proto::CredentialEvents {
     type Key = proto::CredentialEventKey;
 }
 
+#[derive(Debug)]
+pub enum Services {
+    Organizations(proto::OrganizationEventKey, proto::OrganizationEvents),
+    Credentials(proto::CredentialEventKey, proto::CredentialEvents),
+}
+
+impl hub_core::consumer::MessageGroup for Services {
+    const REQUESTED_TOPICS: &'static [&'static str] = &["hub-orgs", "hub-credentials"];
+
+    fn from_message<M: hub_core::consumer::Message>(msg: &M) -> Result<Self, RecvError> {
+        let topic = msg.topic();
+        let key = msg.key().ok_or(RecvError::MissingKey)?;
+        let val = msg.payload().ok_or(RecvError::MissingPayload)?;
+        info!(topic, ?key, ?val);
+
+        match topic {
+            "hub-orgs" => {
+                let key = proto::OrganizationEventKey::decode(key)?;
+                let val = proto::OrganizationEvents::decode(val)?;
+
+                Ok(Services::Organizations(key, val))
+            },
+            "hub-credentials" => {
+                let key = proto::CredentialEventKey::decode(key)?;
+                let val = proto::CredentialEvents::decode(val)?;
+
+                Ok(Services::Credentials(key, val))
+            },
+            t => Err(RecvError::BadTopic(t.into())),
+        }
+    }
+}
+
 #[derive(Debug, clap::Args)]
 #[command(version, author, about)]
 pub struct Args {
-    #[arg(short, long, env, default_value_t = 3002)]
+    #[arg(short, long, env, default_value_t = 3005)]
     pub port: u16,
 
+    #[command(flatten)]
+    pub db: db::DbArgs,
+
     #[command(flatten)]
     pub ory: ory_client::OryArgs,
+
+    #[command(flatten)]
+    pub backend: backend::BackendArgs,
+
+    #[command(flatten)]
+    pub credentials: config::CredentialArgs,
+
+    #[command(flatten)]
+    pub membership: membership::MembershipArgs,
+
+    #[command(flatten)]
+    pub idempotency: idempotency::IdempotencyArgs,
+
+    #[command(flatten)]
+    pub cipher: cipher::CipherArgs,
+
+    #[command(flatten)]
+    pub outbox: outbox::OutboxArgs,
+
+    #[command(flatten)]
+    pub expiry: expiry::ExpiryArgs,
+
+    #[command(flatten)]
+    pub introspection: introspection::IntrospectionArgs,
+
+    #[command(flatten)]
+    pub auth: auth::AuthArgs,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -61,35 +147,113 @@ impl<'a> FromRequest<'a> for UserID {
     }
 }
 
+/// The address and user agent of the client making a request. When the request was proxied the
+/// address is the last `X-Forwarded-For` entry, the one appended by the proxy in front of this
+/// service; earlier entries are supplied by the client and cannot be trusted.
+#[derive(Debug, Clone, Default)]
+pub struct RequestInfo {
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl<'a> FromRequest<'a> for RequestInfo {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        let header = |name| {
+            req.headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+        };
+
+        let ip = header("X-FORWARDED-FOR")
+            .and_then(|value| value.rsplit(',').next())
+            .map(str::trim)
+            .filter(|ip| !ip.is_empty())
+            .map(ToString::to_string)
+            .or_else(|| {
+                req.remote_addr()
+                    .as_socket_addr()
+                    .map(|addr| addr.ip().to_string())
+            });
+
+        Ok(Self {
+            ip,
+            user_agent: header("USER-AGENT").map(ToString::to_string),
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct AppState {
     pub schema: graphql::schema::AppSchema,
-    pub ory: ory_client::Client,
-    pub producer: Producer<CredentialEvents>,
+    pub connection: Connection,
+    pub ory: backend::Backend,
+    pub credentials: config::CredentialArgs,
+    pub membership: membership::Membership,
+    pub idempotency: idempotency::Idempotency,
+    pub cipher: cipher::Cipher,
+    pub introspection: introspection::IntrospectionCache,
 }
 
 impl AppState {
     #[must_use]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         schema: graphql::schema::AppSchema,
-        ory: ory_client::Client,
-        producer: Producer<CredentialEvents>,
+        connection: Connection,
+        ory: backend::Backend,
+        credentials: config::CredentialArgs,
+        membership: membership::Membership,
+        idempotency: idempotency::Idempotency,
+        cipher: cipher::Cipher,
+        introspection: introspection::IntrospectionCache,
     ) -> Self {
         Self {
             schema,
+            connection,
             ory,
-            producer,
+            credentials,
+            membership,
+            idempotency,
+            cipher,
+            introspection,
         }
     }
 }
 
 pub struct AppContext {
+    pub db: Connection,
     pub user_id: Option<Uuid>,
+    pub request: RequestInfo,
+    pub usage_loader: DataLoader<UsageLoader>,
+    pub request_counts_loader: DataLoader<RequestCountsLoader>,
 }
 
 impl AppContext {
     #[must_use]
-    pub fn new(user_id: Option<Uuid>) -> Self {
-        Self { user_id }
+    pub fn new(db: Connection, user_id: Option<Uuid>, request: RequestInfo) -> Self {
+        let usage_loader = DataLoader::new(UsageLoader::new(db.clone()), tokio::spawn);
+        let request_counts_loader =
+            DataLoader::new(RequestCountsLoader::new(db.clone()), tokio::spawn);
+
+        Self {
+            db,
+            user_id,
+            request,
+            usage_loader,
+            request_counts_loader,
+        }
+    }
+
+    /// The actor recorded in the audit log for changes `user_id` makes through this request.
+    #[must_use]
+    pub fn actor(&self, user_id: Uuid) -> audit::Actor {
+        audit::Actor {
+            user_id: Some(user_id),
+            ip: self.request.ip.clone(),
+            user_agent: self.request.user_agent.clone(),
+        }
     }
 }
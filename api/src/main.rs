@@ -1,38 +1,118 @@
 use holaplex_hub_credentials::{
+    auth::ApiKey,
+    backend,
+    cipher::Cipher,
+    db::Connection,
+    events,
+    expiry::Scheduler,
     graphql::schema::build_schema,
-    handlers::{graphql_handler, health, playground},
-    ory_client,
+    handlers::{
+        graphql_handler, health, introspection_handler, playground, token_hook_handler,
+        usage_handler,
+    },
+    idempotency::Idempotency,
+    introspection::IntrospectionCache,
+    membership::Membership,
+    outbox::{metrics_handler, OutboxMetrics, Relay},
     proto::CredentialEvents,
-    AppState,
+    AppState, Args, Services,
 };
-use hub_core::clap;
+use hub_core::{anyhow::Context, futures_util::StreamExt, prelude::*, tokio};
 use poem::{get, listener::TcpListener, middleware::AddData, post, EndpointExt, Route, Server};
 
-#[derive(Debug, clap::Args)]
-#[command(version, author, about)]
-pub struct Args {
-    #[arg(short, long, env, default_value_t = 3005)]
-    pub port: u16,
-
-    #[command(flatten)]
-    pub ory: ory_client::OryArgs,
-}
-
 pub fn main() {
     let opts = hub_core::StartConfig {
         service_name: "hub-credentials",
     };
 
     hub_core::run(opts, |common, args| {
-        let Args { port, ory } = args;
+        let Args {
+            port,
+            db,
+            ory,
+            backend,
+            credentials,
+            membership,
+            idempotency,
+            cipher,
+            outbox,
+            expiry,
+            introspection,
+            auth,
+        } = args;
 
         common.rt.block_on(async move {
+            let connection = Connection::new(db)
+                .await
+                .context("failed to get database connection")?;
             let schema = build_schema();
 
-            let ory = ory_client::Client::new(ory);
+            let ory = backend::build(backend, ory);
             let producer = common.producer_cfg.build::<CredentialEvents>().await?;
+            let membership = Membership::new(membership);
+            let idempotency = Idempotency::new(idempotency)?;
+            let cipher = Cipher::from_args(&cipher)?;
+            let cons = common.consumer_cfg.build::<Services>().await?;
+            let metrics = OutboxMetrics::default();
+            let introspection = IntrospectionCache::new(introspection);
+
+            let state = AppState::new(
+                schema,
+                connection.clone(),
+                ory.clone(),
+                credentials.clone(),
+                membership,
+                idempotency,
+                cipher.clone(),
+                introspection.clone(),
+            );
+
+            tokio::spawn(Relay::new(connection.clone(), producer, metrics.clone(), outbox).run());
+            tokio::spawn(
+                Scheduler::new(
+                    connection.clone(),
+                    ory.clone(),
+                    cipher,
+                    credentials.clone(),
+                    expiry,
+                )
+                .run(),
+            );
+
+            tokio::spawn(async move {
+                let mut stream = cons.stream();
+                loop {
+                    let connection = connection.clone();
+                    let ory = ory.clone();
+                    let credentials = credentials.clone();
+                    let introspection = introspection.clone();
 
-            let state = AppState::new(schema, ory, producer);
+                    match stream.next().await {
+                        Some(Ok(msg)) => {
+                            info!(?msg, "message received");
+
+                            tokio::spawn(async move {
+                                if let Err(e) = events::process(
+                                    msg,
+                                    connection,
+                                    ory,
+                                    credentials,
+                                    introspection,
+                                )
+                                .await
+                                {
+                                    error!("failed to process message: {e:?}");
+                                }
+                            });
+                            tokio::task::yield_now().await;
+                        },
+                        None => (),
+                        Some(Err(e)) => {
+                            warn!("failed to get message {:?}", e);
+                        },
+                    }
+                }
+            });
 
             Server::new(TcpListener::bind(format!("0.0.0.0:{port}")))
                 .run(
@@ -41,6 +121,25 @@ pub fn main() {
                             "/graphql",
                             post(graphql_handler).with(AddData::new(state.clone())),
                         )
+                        .at(
+                            "/usage",
+                            post(usage_handler)
+                                .with(AddData::new(state.clone()))
+                                .with(ApiKey::new(auth.gateway_api_key.clone())),
+                        )
+                        .at(
+                            "/introspect",
+                            post(introspection_handler)
+                                .with(AddData::new(state.clone()))
+                                .with(ApiKey::new(auth.gateway_api_key.clone())),
+                        )
+                        .at(
+                            "/token-hook",
+                            post(token_hook_handler)
+                                .with(AddData::new(state.clone()))
+                                .with(ApiKey::new(auth.token_hook_api_key.clone())),
+                        )
+                        .at("/metrics", get(metrics_handler).with(AddData::new(metrics)))
                         .at("/playground", get(playground))
                         .at("/health", get(health)),
                 )
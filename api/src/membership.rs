@@ -0,0 +1,100 @@
+use hub_core::{anyhow::Result, clap, prelude::*, uuid::Uuid};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+const MEMBERS_QUERY: &str = r"
+query OrganizationMembers($id: UUID!) {
+  organization(id: $id) {
+    members {
+      userId
+    }
+  }
+}
+";
+
+/// Arguments for looking up the members of an organization
+#[derive(Debug, Clone, clap::Args)]
+pub struct MembershipArgs {
+    /// The GraphQL endpoint of the organizations service.
+    #[arg(long, env, default_value = "http://127.0.0.1:3001/graphql")]
+    pub organizations_graphql_endpoint: String,
+}
+
+/// Looks up organization membership from the organizations service
+#[derive(Debug, Clone)]
+pub struct Membership {
+    endpoint: String,
+    http: reqwest::Client,
+}
+
+#[derive(Debug, Serialize)]
+struct Request<'a> {
+    query: &'a str,
+    variables: serde_json::Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    data: Option<ResponseData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResponseData {
+    organization: Option<Organization>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Organization {
+    members: Vec<Member>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Member {
+    user_id: String,
+}
+
+impl Membership {
+    #[must_use]
+    pub fn new(args: MembershipArgs) -> Self {
+        let MembershipArgs {
+            organizations_graphql_endpoint,
+        } = args;
+
+        Self {
+            endpoint: organizations_graphql_endpoint,
+            http: reqwest::Client::new(),
+        }
+    }
+
+    /// Checks whether `user` is a member of `organization`.
+    ///
+    /// # Errors
+    /// Returns an error if the organizations service cannot be reached or responds with an
+    /// unexpected payload.
+    pub async fn is_member(&self, organization: Uuid, user: Uuid) -> Result<bool> {
+        let response: Response = self
+            .http
+            .post(&self.endpoint)
+            .header("X-USER-ID", user.to_string())
+            .json(&Request {
+                query: MEMBERS_QUERY,
+                variables: json!({ "id": organization.to_string() }),
+            })
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let data = response
+            .data
+            .ok_or_else(|| anyhow!("no data in organization members response"))?;
+
+        let user = user.to_string();
+
+        Ok(data
+            .organization
+            .map_or(false, |o| o.members.iter().any(|m| m.user_id == user)))
+    }
+}
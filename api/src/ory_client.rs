@@ -1,27 +1,141 @@
-use hub_core::clap;
+use hub_core::{anyhow::Result as AnyResult, clap, prelude::*};
 use ory_openapi_generated_client::{
     apis::{
         configuration::Configuration,
         o_auth2_api::{
-            create_o_auth2_client, delete_o_auth2_client, get_o_auth2_client, list_o_auth2_clients,
-            oauth2_token_exchange, set_o_auth2_client, CreateOAuth2ClientError,
-            DeleteOAuth2ClientError, GetOAuth2ClientError, ListOAuth2ClientsError,
-            Oauth2TokenExchangeError, SetOAuth2ClientError,
+            create_o_auth2_client, delete_o_auth2_client, delete_o_auth2_token, get_o_auth2_client,
+            introspect_o_auth2_token, list_o_auth2_clients, patch_o_auth2_client,
+            revoke_o_auth2_token, set_o_auth2_client, CreateOAuth2ClientError,
+            DeleteOAuth2ClientError, DeleteOAuth2TokenError, GetOAuth2ClientError,
+            IntrospectOAuth2TokenError, ListOAuth2ClientsError, Oauth2TokenExchangeError,
+            PatchOAuth2ClientError, RevokeOAuth2TokenError, SetOAuth2ClientError,
         },
-        Error,
+        Error, ResponseContent,
     },
-    models::{OAuth2Client, OAuth2TokenExchange},
+    models::{IntrospectedOAuth2Token, JsonPatch, OAuth2Client, OAuth2TokenExchange},
 };
+use poem::async_trait;
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::{header::LINK, Url};
+use serde_json::Value;
+
+use crate::backend::CredentialBackend;
+
+const CLIENT_SECRET_LENGTH: usize = 48;
+
+/// The largest page of clients Hydra returns.
+pub const MAX_PAGE_SIZE: i64 = 500;
+
+/// The grant API credentials exchange their client ID and secret for access tokens with.
+pub const CLIENT_CREDENTIALS_GRANT: &str = "client_credentials";
+
+/// Builds a JSON Patch operation setting the client field at `path`, whether or not it is present.
+#[must_use]
+pub fn add_patch(path: &str, value: Value) -> JsonPatch {
+    JsonPatch {
+        value: Some(value),
+        ..JsonPatch::new("add".to_string(), path.to_string())
+    }
+}
+
+/// Generates a random client secret.
+#[must_use]
+pub fn generate_secret() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(CLIENT_SECRET_LENGTH)
+        .map(char::from)
+        .collect()
+}
+
+/// Formats a token lifetime in seconds as a duration understood by Hydra.
+#[must_use]
+pub fn format_lifespan(seconds: i64) -> String {
+    format!("{seconds}s")
+}
+
+/// Parses a duration as returned by Hydra, e.g. `8760h0m0s`, into whole seconds.
+///
+/// # Errors
+/// Returns an error if the duration is malformed or uses an unknown unit.
+pub fn parse_lifespan(lifespan: &str) -> AnyResult<i64> {
+    const NANOS_IN_A_SECOND: i64 = 1_000_000_000;
+
+    let mut nanos: i64 = 0;
+    let mut rest = lifespan.trim();
+
+    if rest == "0" {
+        return Ok(0);
+    }
+
+    while !rest.is_empty() {
+        let unit_start = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or_else(|| anyhow!("missing unit in lifespan {lifespan:?}"))?;
+        let (value, tail) = rest.split_at(unit_start);
+        let value: i64 = value.parse()?;
+
+        let unit_end = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_end);
+
+        let factor = match unit {
+            "h" => 3600 * NANOS_IN_A_SECOND,
+            "m" => 60 * NANOS_IN_A_SECOND,
+            "s" => NANOS_IN_A_SECOND,
+            "ms" => 1_000_000,
+            "us" | "µs" => 1_000,
+            "ns" => 1,
+            unit => return Err(anyhow!("unknown unit {unit:?} in lifespan {lifespan:?}")),
+        };
+
+        nanos = value
+            .checked_mul(factor)
+            .and_then(|v| nanos.checked_add(v))
+            .ok_or_else(|| anyhow!("numeric overflow on lifespan {lifespan:?}"))?;
+        rest = tail;
+    }
+
+    Ok(nanos / NANOS_IN_A_SECOND)
+}
+
+/// A page of clients listed from Hydra
+#[derive(Debug, Clone)]
+pub struct ClientPage {
+    pub clients: Vec<OAuth2Client>,
+    /// The token of the following page, taken from the `Link` header. `None` on the last page.
+    pub next_page_token: Option<String>,
+    /// The total number of clients matching the query, when Hydra reports it.
+    pub total_count: Option<i64>,
+}
+
+/// Extracts the `page_token` of the `rel="next"` link from a `Link` header.
+fn next_page_token(link: &str) -> Option<String> {
+    link.split(',')
+        .find(|link| link.contains(r#"rel="next""#))
+        .and_then(|link| {
+            let start = link.find('<')? + 1;
+            let end = link.find('>')?;
+
+            Url::parse(link.get(start..end)?).ok()
+        })
+        .and_then(|url| {
+            url.query_pairs()
+                .find(|(k, _)| k == "page_token")
+                .map(|(_, v)| v.into_owned())
+        })
+}
 
 /// Arguments for establishing a database connection
 #[derive(Debug, clap::Args)]
 pub struct OryArgs {
     #[arg(long, env, default_value = "http://127.0.0.1:4445")]
-    ory_admin_base_url: String,
+    pub ory_admin_base_url: String,
     #[arg(long, env, default_value = "http://127.0.0.1:4444")]
-    ory_public_base_url: String,
+    pub ory_public_base_url: String,
     #[arg(long, env, default_value = "")]
-    ory_auth_token: String,
+    pub ory_auth_token: String,
 }
 
 #[derive(Clone, Debug)]
@@ -46,11 +160,14 @@ impl Client {
             auth_token: ory_auth_token,
         }
     }
+}
 
+#[async_trait]
+impl CredentialBackend for Client {
     /// Res
     ///
     /// # Errors
-    pub async fn create_client(
+    async fn create_client(
         &self,
         o_auth2_client: &OAuth2Client,
     ) -> Result<OAuth2Client, Error<CreateOAuth2ClientError>> {
@@ -66,7 +183,7 @@ impl Client {
     /// Res
     ///
     /// # Errors
-    pub async fn update_client(
+    async fn update_client(
         &self,
         id: &str,
         o_auth2_client: &OAuth2Client,
@@ -79,10 +196,28 @@ impl Client {
         set_o_auth2_client(&config, id, o_auth2_client).await
     }
 
+    /// Applies a JSON Patch to the client, leaving every field the patch does not touch as it is.
+    ///
+    /// # Errors
+    /// Returns an error if the Hydra request fails or the patch does not apply.
+    async fn patch_client(
+        &self,
+        id: &str,
+        patch: Vec<JsonPatch>,
+    ) -> Result<OAuth2Client, Error<PatchOAuth2ClientError>> {
+        let config = Configuration {
+            base_path: self.admin_base_url.clone(),
+            bearer_access_token: Some(self.auth_token.clone()),
+            ..Configuration::default()
+        };
+
+        patch_o_auth2_client(&config, id, patch).await
+    }
+
     /// Res
     ///
     /// # Errors
-    pub async fn get_client(
+    async fn get_client(
         &self,
         client_id: &str,
     ) -> Result<OAuth2Client, Error<GetOAuth2ClientError>> {
@@ -98,10 +233,7 @@ impl Client {
     /// Res
     ///
     /// # Errors
-    pub async fn delete_client(
-        &self,
-        client_id: &str,
-    ) -> Result<(), Error<DeleteOAuth2ClientError>> {
+    async fn delete_client(&self, client_id: &str) -> Result<(), Error<DeleteOAuth2ClientError>> {
         let config = Configuration {
             base_path: self.admin_base_url.clone(),
             bearer_access_token: Some(self.auth_token.clone()),
@@ -111,10 +243,27 @@ impl Client {
         delete_o_auth2_client(&config, client_id).await
     }
 
+    /// Revokes every access token issued to the client.
+    ///
+    /// Hydra v2 no longer exposes an endpoint to flush inactive tokens; the admin token deletion
+    /// removes the tokens from storage so they immediately fail introspection.
+    ///
+    /// # Errors
+    /// Returns an error if the request fails.
+    async fn revoke_tokens(&self, client_id: &str) -> Result<(), Error<DeleteOAuth2TokenError>> {
+        let config = Configuration {
+            base_path: self.admin_base_url.clone(),
+            bearer_access_token: Some(self.auth_token.clone()),
+            ..Configuration::default()
+        };
+
+        delete_o_auth2_token(&config, client_id).await
+    }
+
     /// Res
     ///
     /// # Errors
-    pub async fn list_clients(
+    async fn list_clients(
         &self,
         owner: &str,
         page_size: Option<i64>,
@@ -129,20 +278,158 @@ impl Client {
         list_o_auth2_clients(&config, page_size, page_token, None, Some(owner)).await
     }
 
-    /// Res
+    /// Lists a single page of the clients owned by `owner`, including the token of the following
+    /// page. The generated `list_o_auth2_clients` drops the response headers so the request is made
+    /// directly.
+    ///
+    /// # Errors
+    /// Returns an error if the request fails or Hydra responds with an error.
+    async fn list_clients_page(
+        &self,
+        owner: &str,
+        page_size: i64,
+        page_token: Option<&str>,
+    ) -> Result<ClientPage, Error<ListOAuth2ClientsError>> {
+        let config = Configuration {
+            base_path: self.admin_base_url.clone(),
+            bearer_access_token: Some(self.auth_token.clone()),
+            ..Configuration::default()
+        };
+
+        let mut query = vec![
+            ("owner", owner.to_string()),
+            ("page_size", page_size.to_string()),
+        ];
+
+        if let Some(page_token) = page_token {
+            query.push(("page_token", page_token.to_string()));
+        }
+
+        let mut request = config
+            .client
+            .get(format!("{}/admin/clients", config.base_path))
+            .query(&query);
+
+        if let Some(ref token) = config.bearer_access_token {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await?;
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let content = response.text().await?;
+
+        if status.is_client_error() || status.is_server_error() {
+            let entity = serde_json::from_str(&content).ok();
+
+            return Err(Error::ResponseError(ResponseContent {
+                status,
+                content,
+                entity,
+            }));
+        }
+
+        let clients = serde_json::from_str(&content)?;
+
+        let next_page_token = headers
+            .get(LINK)
+            .and_then(|link| link.to_str().ok())
+            .and_then(next_page_token);
+
+        let total_count = headers
+            .get("x-total-count")
+            .and_then(|count| count.to_str().ok())
+            .and_then(|count| count.parse().ok());
+
+        Ok(ClientPage {
+            clients,
+            next_page_token,
+            total_count,
+        })
+    }
+
+    /// Exchanges the client credentials for an access token, requesting `scope` when given.
+    ///
+    /// The generated `oauth2_token_exchange` does not accept a `scope` parameter so the form is
+    /// posted to Hydra's token endpoint directly.
     ///
     /// # Errors
-    pub async fn exchange_token(
+    /// Returns an error if the request fails or Hydra rejects the credentials or scope.
+    async fn exchange_token(
         &self,
         client_id: String,
         client_secret: String,
+        scope: Option<&str>,
     ) -> Result<OAuth2TokenExchange, Error<Oauth2TokenExchangeError>> {
+        let config = Configuration {
+            base_path: self.public_base_url.clone(),
+            ..Configuration::default()
+        };
+
+        let mut form = vec![("grant_type", "client_credentials")];
+
+        if let Some(scope) = scope {
+            form.push(("scope", scope));
+        }
+
+        let response = config
+            .client
+            .post(format!("{}/oauth2/token", config.base_path))
+            .basic_auth(client_id, Some(client_secret))
+            .form(&form)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let content = response.text().await?;
+
+        if status.is_client_error() || status.is_server_error() {
+            let entity = serde_json::from_str(&content).ok();
+
+            return Err(Error::ResponseError(ResponseContent {
+                status,
+                content,
+                entity,
+            }));
+        }
+
+        serde_json::from_str(&content).map_err(Into::into)
+    }
+
+    /// Asks Hydra whether `token` is active and who it was issued to.
+    ///
+    /// # Errors
+    /// Returns an error if the request fails or Hydra rejects it.
+    async fn introspect_token(
+        &self,
+        token: &str,
+    ) -> Result<IntrospectedOAuth2Token, Error<IntrospectOAuth2TokenError>> {
+        let config = Configuration {
+            base_path: self.admin_base_url.clone(),
+            bearer_access_token: Some(self.auth_token.clone()),
+            ..Configuration::default()
+        };
+
+        introspect_o_auth2_token(&config, token, None).await
+    }
+
+    /// Revokes a single access token issued to the client.
+    ///
+    /// # Errors
+    /// Returns an error if the request fails or Hydra rejects the client credentials.
+    async fn revoke_token(
+        &self,
+        client_id: String,
+        client_secret: String,
+        token: &str,
+    ) -> Result<(), Error<RevokeOAuth2TokenError>> {
         let config = Configuration {
             base_path: self.public_base_url.clone(),
             basic_auth: Some((client_id, Some(client_secret))),
             ..Configuration::default()
         };
 
-        oauth2_token_exchange(&config, "client_credentials", None, None, None, None).await
+        revoke_o_auth2_token(&config, token).await
     }
 }
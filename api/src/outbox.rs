@@ -0,0 +1,431 @@
+use std::{
+    collections::HashSet,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicI64, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use hub_core::{
+    anyhow::Result,
+    chrono::{Duration as ChronoDuration, NaiveDateTime, Utc},
+    clap,
+    prelude::*,
+    tokio,
+};
+use poem::{handler, web::Data};
+use prost::Message;
+use sea_orm::{prelude::*, ActiveValue::Set, DbBackend, Statement, TransactionTrait};
+
+use crate::{
+    db::Connection,
+    entities::{credential_event_dead_letters, credential_event_outbox, prelude::*},
+    producer::EventProducer,
+    proto::{credential_events::Event, CredentialEventKey, CredentialEvents},
+};
+
+/// The advisory lock held by the relay draining the outbox, so only one replica publishes at a
+/// time and events keep their order.
+const RELAY_LOCK_ID: i64 = 0x6f75_7462_6f78;
+
+/// The longest delay in seconds between attempts to publish an event.
+const MAX_BACKOFF_SECS: i64 = 300;
+
+/// Takes an advisory lock for the duration of the current transaction without waiting for it.
+const TRY_LOCK: &str = "SELECT pg_try_advisory_xact_lock($1) AS locked";
+
+/// Selects the oldest events of the keys whose first waiting event is due. A key backing off after
+/// a failure is skipped entirely, so it neither publishes out of order nor fills the batch ahead
+/// of the keys that can make progress.
+const DUE: &str = r"
+SELECT * FROM credential_event_outbox
+WHERE partition_key IN (
+    SELECT partition_key FROM (
+        SELECT DISTINCT ON (partition_key) partition_key, next_attempt_at
+        FROM credential_event_outbox
+        ORDER BY partition_key, id
+    ) AS heads
+    WHERE next_attempt_at <= $1
+)
+ORDER BY id
+LIMIT $2
+";
+
+/// Measures the events waiting to be published.
+const BACKLOG: &str =
+    "SELECT COUNT(*) AS backlog, MIN(created_at) AS oldest FROM credential_event_outbox";
+
+/// Arguments for relaying credential events from the outbox to Kafka
+#[derive(Debug, Clone, clap::Args)]
+pub struct OutboxArgs {
+    /// The number of milliseconds the relay waits before polling an empty outbox again.
+    #[arg(long, env, default_value_t = 1000)]
+    pub outbox_poll_interval_ms: u64,
+
+    /// The maximum number of events read from the outbox at once.
+    #[arg(long, env, default_value_t = 100)]
+    pub outbox_batch_size: u64,
+
+    /// The number of failed attempts to publish an event after which it is moved to the
+    /// `credential_event_dead_letters` table, unblocking the events after it.
+    #[arg(long, env, default_value_t = 20)]
+    pub outbox_max_attempts: i32,
+}
+
+/// Records a credential event to be published once the surrounding transaction commits.
+///
+/// # Errors
+/// Returns an error if the database write fails.
+pub async fn enqueue<C: ConnectionTrait>(
+    conn: &C,
+    event: &CredentialEvents,
+    key: &CredentialEventKey,
+) -> Result<()> {
+    let now = Utc::now().naive_utc();
+
+    let active_model = credential_event_outbox::ActiveModel {
+        partition_key: Set(key.id.clone()),
+        key: Set(key.encode_to_vec()),
+        event: Set(event.encode_to_vec()),
+        attempts: Set(0),
+        last_error: Set(None),
+        next_attempt_at: Set(now),
+        created_at: Set(now),
+        ..Default::default()
+    };
+
+    CredentialEventOutbox::insert(active_model)
+        .exec_without_returning(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Records `event` of the credential `client_id`, caused by `user_id`, to be published once the
+/// surrounding transaction commits. `user_id` is empty for changes made by the service itself.
+///
+/// # Errors
+/// Returns an error if the database write fails.
+pub async fn enqueue_event<C: ConnectionTrait>(
+    conn: &C,
+    event: Event,
+    client_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let event = CredentialEvents { event: Some(event) };
+
+    let key = CredentialEventKey {
+        id: client_id.to_string(),
+        user_id: user_id.to_string(),
+    };
+
+    enqueue(conn, &event, &key).await
+}
+
+/// Takes the advisory lock `id` until the transaction `conn` belongs to ends, returning whether it
+/// was free. The background workers use it so only one replica runs them at a time.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn try_lock<C: ConnectionTrait>(conn: &C, id: i64) -> Result<bool> {
+    let locked = conn
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            TRY_LOCK,
+            [id.into()],
+        ))
+        .await?
+        .map(|row| row.try_get::<bool>("", "locked"))
+        .transpose()?
+        .unwrap_or_default();
+
+    Ok(locked)
+}
+
+/// Counters describing the state of the outbox, exposed in the Prometheus text format
+#[derive(Debug, Clone, Default)]
+pub struct OutboxMetrics(Arc<Counters>);
+
+#[derive(Debug, Default)]
+struct Counters {
+    backlog: AtomicI64,
+    oldest_age_seconds: AtomicI64,
+    published: AtomicU64,
+    failed: AtomicU64,
+    dead_lettered: AtomicU64,
+}
+
+impl OutboxMetrics {
+    /// Renders the metrics in the Prometheus text exposition format.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        for (name, kind, help, value) in [
+            (
+                "credential_event_outbox_backlog",
+                "gauge",
+                "Credential events waiting to be published.",
+                self.0.backlog.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "credential_event_outbox_oldest_age_seconds",
+                "gauge",
+                "Age of the oldest credential event waiting to be published.",
+                self.0
+                    .oldest_age_seconds
+                    .load(Ordering::Relaxed)
+                    .to_string(),
+            ),
+            (
+                "credential_event_outbox_published_total",
+                "counter",
+                "Credential events published from the outbox.",
+                self.0.published.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "credential_event_outbox_failed_total",
+                "counter",
+                "Failed attempts to publish credential events.",
+                self.0.failed.load(Ordering::Relaxed).to_string(),
+            ),
+            (
+                "credential_event_outbox_dead_lettered_total",
+                "counter",
+                "Credential events given up on and moved to the dead letter table.",
+                self.0.dead_lettered.load(Ordering::Relaxed).to_string(),
+            ),
+        ] {
+            // writing to a String never fails
+            let _ = writeln!(
+                out,
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}"
+            );
+        }
+
+        out
+    }
+}
+
+/// Serves the outbox metrics for scraping.
+#[handler]
+pub fn metrics_handler(Data(metrics): Data<&OutboxMetrics>) -> String {
+    metrics.render()
+}
+
+/// Publishes the events recorded in the outbox. Events sharing a key are published in the order
+/// they were enqueued; when one fails, the events after it wait until it has been retried. Events
+/// that cannot be decoded or keep failing are moved to the dead letter table.
+pub struct Relay<P> {
+    db: Connection,
+    producer: P,
+    metrics: OutboxMetrics,
+    poll_interval: Duration,
+    batch_size: u64,
+    max_attempts: i32,
+}
+
+impl<P: EventProducer> Relay<P> {
+    #[must_use]
+    pub fn new(db: Connection, producer: P, metrics: OutboxMetrics, args: OutboxArgs) -> Self {
+        let OutboxArgs {
+            outbox_poll_interval_ms,
+            outbox_batch_size,
+            outbox_max_attempts,
+        } = args;
+
+        Self {
+            db,
+            producer,
+            metrics,
+            poll_interval: Duration::from_millis(outbox_poll_interval_ms),
+            batch_size: outbox_batch_size,
+            max_attempts: outbox_max_attempts,
+        }
+    }
+
+    /// Drains the outbox until the task is cancelled. Full batches are followed by another one
+    /// straight away, otherwise the relay waits for the poll interval.
+    pub async fn run(self) {
+        loop {
+            let drained = match self.relay_batch().await {
+                Ok(published) => published < self.batch_size,
+                Err(e) => {
+                    error!("failed to relay credential events: {e:?}");
+
+                    true
+                },
+            };
+
+            if let Err(e) = self.measure_backlog().await {
+                warn!("failed to measure credential event backlog: {e:?}");
+            }
+
+            if drained {
+                tokio::time::sleep(self.poll_interval).await;
+            }
+        }
+    }
+
+    /// Publishes the oldest batch of events of keys that are not backing off, returning how many
+    /// were published.
+    async fn relay_batch(&self) -> Result<u64> {
+        let txn = self.db.get().begin().await?;
+
+        if !try_lock(&txn, RELAY_LOCK_ID).await? {
+            txn.commit().await?;
+
+            return Ok(0);
+        }
+
+        let now = Utc::now().naive_utc();
+
+        let rows = CredentialEventOutbox::find()
+            .from_raw_sql(Statement::from_sql_and_values(DbBackend::Postgres, DUE, [
+                now.into(),
+                i64::try_from(self.batch_size).unwrap_or(i64::MAX).into(),
+            ]))
+            .all(&txn)
+            .await?;
+
+        let mut blocked = HashSet::new();
+        let mut published = 0;
+
+        for row in rows {
+            if blocked.contains(&row.partition_key) {
+                continue;
+            }
+
+            let decoded = CredentialEvents::decode(row.event.as_slice()).and_then(|event| {
+                CredentialEventKey::decode(row.key.as_slice()).map(|key| (event, key))
+            });
+
+            let (event, key) = match decoded {
+                Ok(decoded) => decoded,
+                // an event that cannot be decoded never will be, so retrying it is pointless
+                Err(e) => {
+                    let attempts = row.attempts;
+                    self.dead_letter(&txn, row, attempts, e.to_string()).await?;
+
+                    continue;
+                },
+            };
+
+            match self.producer.publish(&event, &key).await {
+                Ok(()) => {
+                    CredentialEventOutbox::delete_by_id(row.id)
+                        .exec(&txn)
+                        .await?;
+
+                    published += 1;
+                    self.metrics.0.published.fetch_add(1, Ordering::Relaxed);
+                },
+                Err(e) => {
+                    warn!(
+                        "failed to publish credential event {} after {} attempts: {e:?}",
+                        row.id, row.attempts
+                    );
+
+                    self.metrics.0.failed.fetch_add(1, Ordering::Relaxed);
+
+                    let attempts = row.attempts + 1;
+
+                    if attempts >= self.max_attempts {
+                        self.dead_letter(&txn, row, attempts, e.to_string()).await?;
+
+                        continue;
+                    }
+
+                    let active_model = credential_event_outbox::ActiveModel {
+                        id: Set(row.id),
+                        attempts: Set(attempts),
+                        last_error: Set(Some(e.to_string())),
+                        next_attempt_at: Set(now + backoff(attempts)),
+                        ..Default::default()
+                    };
+
+                    active_model.update(&txn).await?;
+                    blocked.insert(row.partition_key);
+                },
+            }
+        }
+
+        txn.commit().await?;
+
+        Ok(published)
+    }
+
+    /// Moves an event the relay gives up on from the outbox to the dead letter table.
+    async fn dead_letter<C: ConnectionTrait>(
+        &self,
+        conn: &C,
+        row: credential_event_outbox::Model,
+        attempts: i32,
+        last_error: String,
+    ) -> Result<()> {
+        error!(
+            "moving credential event {} of {} to the dead letter table after {attempts} attempts: \
+             {last_error}",
+            row.id, row.partition_key
+        );
+
+        let active_model = credential_event_dead_letters::ActiveModel {
+            id: Set(row.id),
+            partition_key: Set(row.partition_key),
+            key: Set(row.key),
+            event: Set(row.event),
+            attempts: Set(attempts),
+            last_error: Set(Some(last_error)),
+            created_at: Set(row.created_at),
+            dead_lettered_at: Set(Utc::now().naive_utc()),
+        };
+
+        CredentialEventDeadLetters::insert(active_model)
+            .exec_without_returning(conn)
+            .await?;
+
+        CredentialEventOutbox::delete_by_id(row.id)
+            .exec(conn)
+            .await?;
+
+        self.metrics.0.dead_lettered.fetch_add(1, Ordering::Relaxed);
+
+        Ok(())
+    }
+
+    async fn measure_backlog(&self) -> Result<()> {
+        let row = self
+            .db
+            .get()
+            .query_one(Statement::from_string(
+                DbBackend::Postgres,
+                BACKLOG.to_string(),
+            ))
+            .await?
+            .ok_or_else(|| anyhow!("no credential event backlog returned"))?;
+
+        let backlog: i64 = row.try_get("", "backlog")?;
+        let oldest: Option<NaiveDateTime> = row.try_get("", "oldest")?;
+        let oldest_age = oldest.map_or(0, |at| (Utc::now().naive_utc() - at).num_seconds());
+
+        self.metrics.0.backlog.store(backlog, Ordering::Relaxed);
+        self.metrics
+            .0
+            .oldest_age_seconds
+            .store(oldest_age, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
+
+/// The delay before the next attempt to publish an event that failed `attempts` times, doubling
+/// with every attempt up to [`MAX_BACKOFF_SECS`].
+fn backoff(attempts: i32) -> ChronoDuration {
+    let secs = 2_i64
+        .checked_pow(attempts.unsigned_abs())
+        .map_or(MAX_BACKOFF_SECS, |secs| secs.min(MAX_BACKOFF_SECS));
+
+    ChronoDuration::seconds(secs)
+}
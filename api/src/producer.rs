@@ -0,0 +1,48 @@
+use hub_core::{anyhow::Result, chrono::Utc, producer::Producer};
+use poem::async_trait;
+
+use crate::{
+    graphql::objects::Credential,
+    proto::{self, CredentialEventKey, CredentialEvents},
+};
+
+/// A destination credential events are published to
+#[async_trait]
+pub trait EventProducer: Send + Sync {
+    /// Publishes `event` under `key`.
+    ///
+    /// # Errors
+    /// Returns an error if the event could not be delivered.
+    async fn publish(&self, event: &CredentialEvents, key: &CredentialEventKey) -> Result<()>;
+}
+
+#[async_trait]
+impl EventProducer for Producer<CredentialEvents> {
+    async fn publish(&self, event: &CredentialEvents, key: &CredentialEventKey) -> Result<()> {
+        self.send(Some(event), Some(key)).await?;
+
+        Ok(())
+    }
+}
+
+/// Builds the payload of a credential event recording that `actor` changed `credential` from
+/// `before` to `after`. Either snapshot is left out when the credential did not exist at that
+/// point, and both are the same when the event does not change the credential.
+#[must_use]
+pub fn payload(
+    actor: &str,
+    credential: &Credential,
+    before: Option<&Credential>,
+    after: Option<&Credential>,
+) -> proto::OAuth2Client {
+    proto::OAuth2Client {
+        user_id: actor.to_string(),
+        client_name: credential.name.clone(),
+        organization: credential.organization_id.to_string(),
+        client_id: credential.client_id.clone(),
+        scopes: credential.scopes.iter().map(ToString::to_string).collect(),
+        occurred_at: Utc::now().timestamp(),
+        before: before.map(Into::into),
+        after: after.map(Into::into),
+    }
+}
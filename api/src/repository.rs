@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+
+use hub_core::{
+    anyhow::Result,
+    chrono::{Duration, NaiveDateTime, Utc},
+    uuid::Uuid,
+};
+use ory_openapi_generated_client::{apis::Error as OryError, models::OAuth2Client};
+use sea_orm::{prelude::*, sea_query::OnConflict, ActiveValue::Set, DbBackend, Statement};
+use serde_json::json;
+
+use crate::{
+    backend::Backend,
+    db::Connection,
+    entities::{credentials, prelude::*, sea_orm_active_enums::CredentialStatus},
+    graphql::objects::Credential,
+    ory_client::{add_patch, CLIENT_CREDENTIALS_GRANT},
+    usage,
+};
+
+/// The first key of the advisory locks taken on organizations, keeping them apart from the
+/// single-key locks of the background workers.
+const ORGANIZATION_LOCK_CLASS: i32 = 0x7175_6f74;
+
+/// Takes the advisory lock of an organization until the current transaction ends, waiting for it
+/// if another transaction holds it.
+const LOCK_ORGANIZATION: &str = "SELECT pg_advisory_xact_lock($1, hashtext($2))";
+
+/// Lists the organizations with credentials that have not been deleted.
+const ORGANIZATIONS: &str =
+    "SELECT DISTINCT organization_id FROM credentials WHERE deleted_at IS NULL";
+
+/// Gives the credentials of an organization without an expiry one, a number of seconds after they
+/// were created but no earlier than `$3`.
+const BACKFILL_EXPIRY: &str = r"
+UPDATE credentials
+SET expires_at = GREATEST(created_at + $2 * INTERVAL '1 second', $3)
+WHERE organization_id = $1
+AND expires_at IS NULL
+AND deleted_at IS NULL
+";
+
+/// Builds the metadata row of a credential from what Ory knows about it, for credentials created
+/// before this service stored their metadata.
+#[must_use]
+pub fn backfill(credential: &Credential) -> credentials::Model {
+    let Credential {
+        name,
+        description,
+        client_id,
+        created_by_id,
+        organization_id,
+        created_at,
+        updated_at,
+        updated_by_id,
+        scopes,
+        status,
+        expires_at,
+        ..
+    } = credential.clone();
+
+    credentials::Model {
+        client_id,
+        organization_id,
+        name,
+        description,
+        scopes: scopes.iter().map(ToString::to_string).collect(),
+        status: status.into(),
+        created_by_id,
+        created_at,
+        updated_at,
+        updated_by_id,
+        deleted_by_id: None,
+        deleted_at: None,
+        expires_at,
+        expiry_warning_days: None,
+        client_deleted_at: None,
+    }
+}
+
+/// Inserts the metadata of a credential, replacing any row already stored for its client ID.
+///
+/// # Errors
+/// Returns an error if the database write fails.
+pub async fn save<C: ConnectionTrait>(conn: &C, model: credentials::Model) -> Result<()> {
+    let active_model: credentials::ActiveModel = model.into();
+
+    Credentials::insert(active_model)
+        .on_conflict(
+            OnConflict::column(credentials::Column::ClientId)
+                .update_columns([
+                    credentials::Column::OrganizationId,
+                    credentials::Column::Name,
+                    credentials::Column::Description,
+                    credentials::Column::Scopes,
+                    credentials::Column::Status,
+                    credentials::Column::CreatedById,
+                    credentials::Column::CreatedAt,
+                    credentials::Column::UpdatedAt,
+                    credentials::Column::UpdatedById,
+                    credentials::Column::DeletedById,
+                    credentials::Column::DeletedAt,
+                    credentials::Column::ExpiresAt,
+                    credentials::Column::ExpiryWarningDays,
+                    credentials::Column::ClientDeletedAt,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Inserts the metadata of a credential unless a row, deleted or not, is already stored for its
+/// client ID.
+///
+/// # Errors
+/// Returns an error if the database write fails.
+pub async fn insert_missing<C: ConnectionTrait>(conn: &C, model: credentials::Model) -> Result<()> {
+    let active_model: credentials::ActiveModel = model.into();
+
+    Credentials::insert(active_model)
+        .on_conflict(
+            OnConflict::column(credentials::Column::ClientId)
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Looks up the metadata of a credential that has not been deleted.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn find<C: ConnectionTrait>(
+    conn: &C,
+    client_id: &str,
+) -> Result<Option<credentials::Model>> {
+    let model = Credentials::find_by_id(client_id.to_string())
+        .filter(credentials::Column::DeletedAt.is_null())
+        .one(conn)
+        .await?;
+
+    Ok(model)
+}
+
+/// Looks up the metadata of the credentials among `client_ids` that have not been deleted.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn find_many<C: ConnectionTrait>(
+    conn: &C,
+    client_ids: Vec<String>,
+) -> Result<HashMap<String, credentials::Model>> {
+    let models = Credentials::find()
+        .filter(credentials::Column::ClientId.is_in(client_ids))
+        .filter(credentials::Column::DeletedAt.is_null())
+        .all(conn)
+        .await?;
+
+    Ok(models
+        .into_iter()
+        .map(|m| (m.client_id.clone(), m))
+        .collect())
+}
+
+/// Looks up the credentials that expired at or before `now` and have not been deleted. Suspended
+/// credentials are only included if `include_suspended` is set.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn find_expired<C: ConnectionTrait>(
+    conn: &C,
+    now: NaiveDateTime,
+    include_suspended: bool,
+) -> Result<Vec<credentials::Model>> {
+    let mut query = Credentials::find()
+        .filter(credentials::Column::ExpiresAt.lte(now))
+        .filter(credentials::Column::DeletedAt.is_null());
+
+    if !include_suspended {
+        query = query.filter(credentials::Column::Status.eq(CredentialStatus::Active));
+    }
+
+    Ok(query.all(conn).await?)
+}
+
+/// Locks the credentials of `organization` until the transaction `conn` belongs to ends, so
+/// changes to how many of them are active are made one at a time.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn lock_organization<C: ConnectionTrait>(conn: &C, organization: Uuid) -> Result<()> {
+    conn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        LOCK_ORGANIZATION,
+        [
+            ORGANIZATION_LOCK_CLASS.into(),
+            organization.to_string().into(),
+        ],
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Counts the active credentials of `organization` that have not been deleted.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn count_active<C: ConnectionTrait>(conn: &C, organization: Uuid) -> Result<u64> {
+    let count = Credentials::find()
+        .filter(credentials::Column::OrganizationId.eq(organization))
+        .filter(credentials::Column::Status.eq(CredentialStatus::Active))
+        .filter(credentials::Column::DeletedAt.is_null())
+        .count(conn)
+        .await?;
+
+    Ok(count)
+}
+
+/// Lists the organizations with credentials that have not been deleted.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn organizations<C: ConnectionTrait>(conn: &C) -> Result<Vec<Uuid>> {
+    let rows = conn
+        .query_all(Statement::from_string(
+            DbBackend::Postgres,
+            ORGANIZATIONS.to_string(),
+        ))
+        .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| row.try_get("", "organization_id"))
+        .collect::<Result<_, _>>()?)
+}
+
+/// Sets the expiry of the credentials of `organization` that have none to `lifetime` after they
+/// were created, or to `earliest` if that is later, returning how many were changed.
+///
+/// # Errors
+/// Returns an error if the database write fails.
+pub async fn backfill_expiry<C: ConnectionTrait>(
+    conn: &C,
+    organization: Uuid,
+    lifetime: Duration,
+    earliest: NaiveDateTime,
+) -> Result<u64> {
+    let res = conn
+        .execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            BACKFILL_EXPIRY,
+            [
+                organization.into(),
+                lifetime.num_seconds().into(),
+                earliest.into(),
+            ],
+        ))
+        .await?;
+
+    Ok(res.rows_affected())
+}
+
+/// Looks up the active credentials expiring after `now` and no later than `until`.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn find_expiring<C: ConnectionTrait>(
+    conn: &C,
+    now: NaiveDateTime,
+    until: NaiveDateTime,
+) -> Result<Vec<credentials::Model>> {
+    let models = Credentials::find()
+        .filter(credentials::Column::ExpiresAt.gt(now))
+        .filter(credentials::Column::ExpiresAt.lte(until))
+        .filter(credentials::Column::DeletedAt.is_null())
+        .filter(credentials::Column::Status.eq(CredentialStatus::Active))
+        .all(conn)
+        .await?;
+
+    Ok(models)
+}
+
+/// Records that the owners of a credential were warned `days` before it expires.
+///
+/// # Errors
+/// Returns an error if the database write fails.
+pub async fn record_expiry_warning<C: ConnectionTrait>(
+    conn: &C,
+    client_id: &str,
+    days: i32,
+) -> Result<()> {
+    let active_model = credentials::ActiveModel {
+        expiry_warning_days: Set(Some(days)),
+        ..Default::default()
+    };
+
+    Credentials::update_many()
+        .set(active_model)
+        .filter(credentials::Column::ClientId.eq(client_id))
+        .exec(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Suspends or reactivates a credential by removing or restoring the `client_credentials` grant,
+/// which Hydra checks on every token exchange, and records the status in its metadata. Clients
+/// created before their metadata was stored get a metadata row. Suspension also revokes the
+/// outstanding tokens of the credential. Returns the credential after the change.
+///
+/// # Errors
+/// Returns an error if the client is malformed or Ory or the database is unavailable.
+pub async fn set_status<C: ConnectionTrait>(
+    conn: &C,
+    ory: &Backend,
+    credential: &Credential,
+    status: CredentialStatus,
+    updated_by_id: Option<Uuid>,
+) -> Result<Credential> {
+    let client_id = &credential.client_id;
+
+    let mut metadata = find(conn, client_id)
+        .await?
+        .unwrap_or_else(|| backfill(credential));
+
+    metadata.status = status;
+    metadata.updated_at = Some(Utc::now().naive_utc());
+    metadata.updated_by_id = updated_by_id;
+
+    save(conn, metadata.clone()).await?;
+
+    let o_auth2_client = match status {
+        CredentialStatus::Active => {
+            ory.patch_client(client_id, vec![add_patch(
+                "/grant_types",
+                json!([CLIENT_CREDENTIALS_GRANT]),
+            )])
+            .await?
+        },
+        CredentialStatus::Suspended => disable_client(ory, client_id).await?,
+    };
+
+    Ok(Credential::try_from(o_auth2_client)?.with_metadata(Some(metadata)))
+}
+
+/// Deletes a credential, leaving a tombstone of its metadata, and disables its client so it can
+/// no longer get tokens. Clients created before their metadata was stored get a tombstone as well.
+/// The client itself is only removed with `delete_client` once the tombstone is committed.
+///
+/// # Errors
+/// Returns an error if Ory or the database is unavailable.
+pub async fn delete<C: ConnectionTrait>(
+    conn: &C,
+    ory: &Backend,
+    credential: &Credential,
+    deleted_by_id: Option<Uuid>,
+) -> Result<()> {
+    insert_missing(conn, backfill(credential)).await?;
+    tombstone(conn, &credential.client_id, deleted_by_id).await?;
+
+    disable_client(ory, &credential.client_id).await?;
+
+    Ok(())
+}
+
+/// Marks a credential as deleted. The row is kept as a tombstone recording who deleted it.
+///
+/// # Errors
+/// Returns an error if the database write fails.
+pub async fn tombstone<C: ConnectionTrait>(
+    conn: &C,
+    client_id: &str,
+    deleted_by_id: Option<Uuid>,
+) -> Result<()> {
+    let active_model = credentials::ActiveModel {
+        deleted_by_id: Set(deleted_by_id),
+        deleted_at: Set(Some(Utc::now().naive_utc())),
+        ..Default::default()
+    };
+
+    Credentials::update_many()
+        .set(active_model)
+        .filter(credentials::Column::ClientId.eq(client_id))
+        .filter(credentials::Column::DeletedAt.is_null())
+        .exec(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Stops the client `client_id` from getting tokens by removing its grants, which Hydra checks on
+/// every token exchange, and revokes the tokens it already has. Returns the updated client.
+///
+/// # Errors
+/// Returns an error if the client does not exist or Ory is unavailable.
+pub async fn disable_client(ory: &Backend, client_id: &str) -> Result<OAuth2Client> {
+    let o_auth2_client = ory
+        .patch_client(client_id, vec![add_patch("/grant_types", json!([]))])
+        .await?;
+
+    ory.revoke_tokens(client_id).await?;
+
+    Ok(o_auth2_client)
+}
+
+/// Looks up the deleted credentials whose client has not been removed from Ory yet.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn find_undeleted_clients<C: ConnectionTrait>(
+    conn: &C,
+) -> Result<Vec<credentials::Model>> {
+    let models = Credentials::find()
+        .filter(credentials::Column::DeletedAt.is_not_null())
+        .filter(credentials::Column::ClientDeletedAt.is_null())
+        .all(conn)
+        .await?;
+
+    Ok(models)
+}
+
+/// Removes the client of a deleted credential from Ory along with its usage, then records that it
+/// is gone so it is not retried. A client that no longer exists counts as removed.
+///
+/// # Errors
+/// Returns an error if Ory or the database is unavailable.
+pub async fn delete_client(db: &Connection, ory: &Backend, client_id: &str) -> Result<()> {
+    match ory.delete_client(client_id).await {
+        Ok(()) => (),
+        Err(OryError::ResponseError(res)) if res.status.as_u16() == 404 => (),
+        Err(e) => return Err(e.into()),
+    }
+
+    usage::delete(db, client_id).await?;
+
+    let active_model = credentials::ActiveModel {
+        client_deleted_at: Set(Some(Utc::now().naive_utc())),
+        ..Default::default()
+    };
+
+    Credentials::update_many()
+        .set(active_model)
+        .filter(credentials::Column::ClientId.eq(client_id))
+        .exec(db.get())
+        .await?;
+
+    Ok(())
+}
+
+/// Builds a credential from its Ory client and the metadata stored for it.
+///
+/// # Errors
+/// Returns an error if the Ory client is malformed or the database query fails.
+pub async fn load_credential<C: ConnectionTrait>(
+    conn: &C,
+    o_auth2_client: OAuth2Client,
+) -> Result<Credential> {
+    let credential: Credential = o_auth2_client.try_into()?;
+    let metadata = find(conn, &credential.client_id).await?;
+
+    Ok(credential.with_metadata(metadata))
+}
+
+/// Builds credentials from their Ory clients and the metadata stored for them, reading the
+/// metadata in a single query.
+///
+/// # Errors
+/// Returns an error if an Ory client is malformed or the database query fails.
+pub async fn load_credentials<C: ConnectionTrait>(
+    conn: &C,
+    o_auth2_clients: Vec<OAuth2Client>,
+) -> Result<Vec<Credential>> {
+    let credentials = o_auth2_clients
+        .into_iter()
+        .map(Credential::try_from)
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut metadata = find_many(
+        conn,
+        credentials.iter().map(|c| c.client_id.clone()).collect(),
+    )
+    .await?;
+
+    Ok(credentials
+        .into_iter()
+        .map(|c| {
+            let m = metadata.remove(&c.client_id);
+
+            c.with_metadata(m)
+        })
+        .collect())
+}
@@ -0,0 +1,183 @@
+use hub_core::{
+    anyhow::Result,
+    chrono::{NaiveDateTime, Utc},
+    prelude::*,
+};
+use ory_openapi_generated_client::apis::Error as OryError;
+use sea_orm::{prelude::*, ActiveValue::Set, QueryOrder, QuerySelect, TransactionTrait};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audit::{self, Actor},
+    backend::Backend,
+    cipher::Cipher,
+    entities::{prelude::*, sea_orm_active_enums::CredentialAuditAction, token_revocations},
+    repository,
+};
+
+/// The largest number of tokens revoked in a single pass.
+const BATCH_SIZE: u64 = 100;
+
+/// What is needed to revoke a single access token, stored encrypted
+#[derive(Serialize, Deserialize)]
+struct RevocationCredentials {
+    client_secret: String,
+    token: String,
+}
+
+/// Records that `token`, issued to `client_id`, must be revoked at `revoke_at`. The token and the
+/// client secret needed to revoke it are encrypted with `cipher`.
+///
+/// # Errors
+/// Returns an error if encryption or the database write fails.
+pub async fn schedule<C: ConnectionTrait>(
+    conn: &C,
+    cipher: &Cipher,
+    client_id: &str,
+    client_secret: String,
+    token: String,
+    revoke_at: NaiveDateTime,
+) -> Result<()> {
+    let credentials = cipher.encrypt(&serde_json::to_vec(&RevocationCredentials {
+        client_secret,
+        token,
+    })?)?;
+
+    let active_model = token_revocations::ActiveModel {
+        client_id: Set(client_id.to_string()),
+        credentials: Set(credentials),
+        revoke_at: Set(revoke_at),
+        attempts: Set(0),
+        last_error: Set(None),
+        created_at: Set(Utc::now().naive_utc()),
+        ..Default::default()
+    };
+
+    TokenRevocations::insert(active_model)
+        .exec_without_returning(conn)
+        .await?;
+
+    Ok(())
+}
+
+/// Revokes the tokens whose revocation is due, recording each in the audit log of its credential so
+/// cached introspections of the token are dropped. Failed revocations are kept and retried on the
+/// next pass.
+///
+/// # Errors
+/// Returns an error if the database queries fail.
+pub async fn revoke_due<C: ConnectionTrait + TransactionTrait>(
+    conn: &C,
+    ory: &Backend,
+    cipher: &Cipher,
+) -> Result<()> {
+    let due = TokenRevocations::find()
+        .filter(token_revocations::Column::RevokeAt.lte(Utc::now().naive_utc()))
+        .order_by_asc(token_revocations::Column::RevokeAt)
+        .limit(BATCH_SIZE)
+        .all(conn)
+        .await?;
+
+    for row in due {
+        let credentials = match decrypt(cipher, &row.credentials) {
+            Ok(credentials) => credentials,
+            // the row was encrypted under another key, which no later pass will have either
+            Err(e) => {
+                error!(
+                    "dropping token revocation {} of client {}: {e:?}",
+                    row.id, row.client_id
+                );
+
+                TokenRevocations::delete_by_id(row.id).exec(conn).await?;
+                continue;
+            },
+        };
+
+        match revoke(ory, &row.client_id, credentials).await {
+            Ok(()) => {
+                if let Err(e) = complete(conn, ory, row.id, &row.client_id).await {
+                    error!(
+                        "failed to record token revocation {} of client {}: {e:?}",
+                        row.id, row.client_id
+                    );
+                }
+            },
+            Err(e) => {
+                warn!(
+                    "failed to revoke access token of client {} after {} attempts: {e:?}",
+                    row.client_id, row.attempts
+                );
+
+                let active_model = token_revocations::ActiveModel {
+                    id: Set(row.id),
+                    attempts: Set(row.attempts + 1),
+                    last_error: Set(Some(e.to_string())),
+                    ..Default::default()
+                };
+
+                active_model.update(conn).await?;
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Records the revocation `id` of a token of `client_id` in the audit log and removes it from the
+/// pending revocations. A credential that no longer exists was deleted, which already revoked the
+/// token and was recorded then.
+async fn complete<C: TransactionTrait>(
+    conn: &C,
+    ory: &Backend,
+    id: i64,
+    client_id: &str,
+) -> Result<()> {
+    let o_auth2_client = match ory.get_client(client_id).await {
+        Ok(o_auth2_client) => Some(o_auth2_client),
+        Err(OryError::ResponseError(res)) if res.status.as_u16() == 404 => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    let txn = conn.begin().await?;
+
+    if let Some(o_auth2_client) = o_auth2_client {
+        let credential = repository::load_credential(&txn, o_auth2_client).await?;
+
+        audit::record(
+            &txn,
+            &Actor::default(),
+            CredentialAuditAction::TokenRevoked,
+            &credential,
+            audit::diff(Some(&credential), Some(&credential)),
+        )
+        .await?;
+    }
+
+    TokenRevocations::delete_by_id(id).exec(&txn).await?;
+
+    txn.commit().await?;
+
+    Ok(())
+}
+
+fn decrypt(cipher: &Cipher, credentials: &[u8]) -> Result<RevocationCredentials> {
+    Ok(serde_json::from_slice(&cipher.decrypt(credentials)?)?)
+}
+
+async fn revoke(ory: &Backend, client_id: &str, credentials: RevocationCredentials) -> Result<()> {
+    let RevocationCredentials {
+        client_secret,
+        token,
+    } = credentials;
+
+    match ory
+        .revoke_token(client_id.to_string(), client_secret, &token)
+        .await
+    {
+        Ok(()) => Ok(()),
+        // the secret no longer authenticates once the credential was rotated or deleted, both of
+        // which already revoked every token of the credential
+        Err(OryError::ResponseError(res)) if res.status.as_u16() == 401 => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
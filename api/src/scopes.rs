@@ -0,0 +1,121 @@
+use std::fmt;
+
+use async_graphql::Enum;
+use hub_core::{
+    anyhow::{Error, Result},
+    prelude::*,
+};
+
+/// A permission that can be granted to an API credential.
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Scope {
+    /// Read projects belonging to the organization.
+    ProjectsRead,
+    /// Create and edit projects belonging to the organization.
+    ProjectsWrite,
+    /// Read drops and their mint history.
+    DropsRead,
+    /// Create, edit, pause and mint drops.
+    DropsWrite,
+    /// Read customers of a project.
+    CustomersRead,
+    /// Create customers of a project.
+    CustomersWrite,
+    /// Read customer wallets and their balances.
+    WalletsRead,
+    /// Create wallets and transfer assets out of them.
+    WalletsWrite,
+    /// Read webhooks configured for the organization.
+    WebhooksRead,
+    /// Create, edit and delete webhooks for the organization.
+    WebhooksWrite,
+}
+
+impl Scope {
+    /// The full catalogue of scopes a credential can be granted.
+    pub const ALL: [Scope; 10] = [
+        Scope::ProjectsRead,
+        Scope::ProjectsWrite,
+        Scope::DropsRead,
+        Scope::DropsWrite,
+        Scope::CustomersRead,
+        Scope::CustomersWrite,
+        Scope::WalletsRead,
+        Scope::WalletsWrite,
+        Scope::WebhooksRead,
+        Scope::WebhooksWrite,
+    ];
+
+    /// The `OAuth2` scope string for the permission, e.g. `drops:read`.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ProjectsRead => "projects:read",
+            Self::ProjectsWrite => "projects:write",
+            Self::DropsRead => "drops:read",
+            Self::DropsWrite => "drops:write",
+            Self::CustomersRead => "customers:read",
+            Self::CustomersWrite => "customers:write",
+            Self::WalletsRead => "wallets:read",
+            Self::WalletsWrite => "wallets:write",
+            Self::WebhooksRead => "webhooks:read",
+            Self::WebhooksWrite => "webhooks:write",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| anyhow!("unknown scope {s:?}"))
+    }
+}
+
+/// Sorts and deduplicates a list of requested scopes.
+#[must_use]
+pub fn normalize(mut scopes: Vec<Scope>) -> Vec<Scope> {
+    scopes.sort_unstable();
+    scopes.dedup();
+
+    scopes
+}
+
+/// Joins scopes into the space-delimited form used by `OAuth2`.
+#[must_use]
+pub fn join(scopes: &[Scope]) -> String {
+    scopes
+        .iter()
+        .map(|s| s.as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The scope to request when exchanging a token limited to `scopes`. Nothing is requested for an
+/// empty list, so Hydra issues the scopes a client created before scopes were introduced was
+/// registered with instead of refusing catalogued ones it was never given.
+#[must_use]
+pub fn request(scopes: &[Scope]) -> Option<String> {
+    Some(join(scopes)).filter(|scope| !scope.is_empty())
+}
+
+/// Parses a space-delimited `OAuth2` scope string, skipping any scope that is not part of the
+/// catalogue (e.g. the `openid offline` defaults Hydra assigns to unscoped clients).
+#[must_use]
+pub fn parse(scope: &str) -> Vec<Scope> {
+    normalize(
+        scope
+            .split_whitespace()
+            .filter_map(|s| s.parse().ok())
+            .collect(),
+    )
+}
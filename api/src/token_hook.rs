@@ -0,0 +1,123 @@
+use hub_core::{anyhow::Result, chrono::Utc, prelude::*, uuid::Uuid};
+use ory_openapi_generated_client::apis::Error as OryError;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    backend::Backend, db::Connection, graphql::objects::CredentialStatus,
+    ory_client::CLIENT_CREDENTIALS_GRANT, repository,
+};
+
+/// The body Hydra sends to the token hook before it issues a token
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenHookRequest {
+    pub request: TokenRequest,
+}
+
+/// The token request being processed by Hydra
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenRequest {
+    /// The client ID of the credential requesting a token.
+    pub client_id: String,
+    /// The grant types used by the request.
+    #[serde(default)]
+    pub grant_types: Vec<String>,
+}
+
+/// The session Hydra merges into the token it issues
+#[derive(Debug, Clone, Serialize)]
+pub struct TokenHookResponse {
+    pub session: Session,
+}
+
+/// The claims to add to each part of the session
+#[derive(Debug, Clone, Serialize)]
+pub struct Session {
+    pub access_token: AccessTokenClaims,
+}
+
+/// The extra claims added to access tokens issued to a credential
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessTokenClaims {
+    /// The ID of the organization the credential belongs to.
+    pub organization_id: Uuid,
+    /// The client ID of the credential.
+    pub credential_id: String,
+    /// The ID of the user who created the credential.
+    pub created_by_id: Uuid,
+    /// The permissions granted to the credential.
+    pub scopes: Vec<String>,
+}
+
+/// How Hydra should proceed with a token request
+#[derive(Debug, Clone)]
+pub enum Decision {
+    /// Issue the token without extra claims, for grants other than client credentials and clients
+    /// not owned by an organization.
+    Unchanged,
+    /// Issue the token with the given claims.
+    Allow(TokenHookResponse),
+    /// Refuse to issue the token for the given reason.
+    Deny(String),
+}
+
+/// Decides whether a token may be issued for `request` and which claims it carries. Client
+/// credentials tokens are only issued to active, unexpired credentials.
+///
+/// # Errors
+/// Returns an error if the Ory client is malformed or the Ory request or database query fails.
+pub async fn decide(db: &Connection, ory: &Backend, request: TokenRequest) -> Result<Decision> {
+    let TokenRequest {
+        client_id,
+        grant_types,
+    } = request;
+
+    if !grant_types.iter().any(|g| g == CLIENT_CREDENTIALS_GRANT) {
+        return Ok(Decision::Unchanged);
+    }
+
+    let o_auth2_client = match ory.get_client(&client_id).await {
+        Ok(o_auth2_client) => o_auth2_client,
+        Err(OryError::ResponseError(res)) if res.status.as_u16() == 404 => {
+            return Ok(Decision::Deny(format!("credential {client_id} not found")));
+        },
+        Err(e) => return Err(e.into()),
+    };
+
+    // clients not created through the Hub, such as those of other services, have no organization
+    // as their owner and are none of this hook's business
+    if o_auth2_client
+        .owner
+        .as_deref()
+        .map_or(true, |owner| Uuid::from_str(owner).is_err())
+    {
+        return Ok(Decision::Unchanged);
+    }
+
+    let credential = repository::load_credential(db.get(), o_auth2_client).await?;
+
+    if credential.status == CredentialStatus::Suspended {
+        return Ok(Decision::Deny(format!(
+            "credential {client_id} is suspended"
+        )));
+    }
+
+    if credential
+        .expires_at
+        .map_or(false, |at| at <= Utc::now().naive_utc())
+    {
+        return Ok(Decision::Deny(format!(
+            "credential {client_id} has expired"
+        )));
+    }
+
+    Ok(Decision::Allow(TokenHookResponse {
+        session: Session {
+            access_token: AccessTokenClaims {
+                organization_id: credential.organization_id,
+                credential_id: credential.client_id,
+                created_by_id: credential.created_by_id,
+                scopes: credential.scopes.iter().map(ToString::to_string).collect(),
+            },
+        },
+    }))
+}
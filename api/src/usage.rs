@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use hub_core::{
+    anyhow::Result,
+    chrono::{NaiveDate, NaiveDateTime, Utc},
+    prelude::*,
+};
+use sea_orm::{prelude::*, DbBackend, Statement, TransactionTrait};
+use serde::Deserialize;
+
+use crate::{
+    db::Connection,
+    entities::{credential_usage, credential_usage_buckets, prelude::*},
+};
+
+/// Records the most recent use of a credential, ignoring reports older than the one stored.
+const UPSERT_LAST_USED: &str = r"
+INSERT INTO credential_usage (client_id, last_used_at, last_used_ip)
+VALUES ($1, $2, $3)
+ON CONFLICT (client_id) DO UPDATE
+SET last_used_at = EXCLUDED.last_used_at, last_used_ip = EXCLUDED.last_used_ip
+WHERE credential_usage.last_used_at < EXCLUDED.last_used_at
+";
+
+/// Adds requests to the daily bucket of a credential.
+const INCREMENT_BUCKET: &str = r"
+INSERT INTO credential_usage_buckets (client_id, day, requests)
+VALUES ($1, $2, $3)
+ON CONFLICT (client_id, day) DO UPDATE
+SET requests = credential_usage_buckets.requests + EXCLUDED.requests
+";
+
+/// A single request made with a credential, reported by the API gateway
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    /// The client ID of the credential the request was authenticated with.
+    pub client_id: String,
+    /// The IP address the request was made from.
+    pub ip: Option<String>,
+    /// The unix timestamp of the request. Defaults to the time the report is received.
+    pub used_at: Option<i64>,
+}
+
+/// Records a batch of usage reports, updating the last use and daily request counts of each
+/// credential in a single transaction.
+///
+/// # Errors
+/// Returns an error if a report has an out of range timestamp or the database writes fail.
+pub async fn record(db: &Connection, reports: Vec<UsageReport>) -> Result<()> {
+    let now = Utc::now().naive_utc();
+
+    let mut last_used: HashMap<String, (NaiveDateTime, Option<String>)> = HashMap::new();
+    let mut requests: HashMap<(String, NaiveDate), i64> = HashMap::new();
+
+    for UsageReport {
+        client_id,
+        ip,
+        used_at,
+    } in reports
+    {
+        let used_at = used_at
+            .map(|secs| {
+                NaiveDateTime::from_timestamp_opt(secs, 0)
+                    .ok_or_else(|| anyhow!("invalid usage timestamp {secs}"))
+            })
+            .transpose()?
+            .unwrap_or(now);
+
+        *requests
+            .entry((client_id.clone(), used_at.date()))
+            .or_default() += 1;
+
+        match last_used.get(&client_id) {
+            Some((at, _)) if *at >= used_at => (),
+            _ => {
+                last_used.insert(client_id, (used_at, ip));
+            },
+        }
+    }
+
+    let txn = db.get().begin().await?;
+
+    for (client_id, (used_at, ip)) in last_used {
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            UPSERT_LAST_USED,
+            [client_id.into(), used_at.into(), ip.into()],
+        ))
+        .await?;
+    }
+
+    for ((client_id, day), count) in requests {
+        txn.execute(Statement::from_sql_and_values(
+            DbBackend::Postgres,
+            INCREMENT_BUCKET,
+            [client_id.into(), day.into(), count.into()],
+        ))
+        .await?;
+    }
+
+    txn.commit().await?;
+
+    Ok(())
+}
+
+/// Looks up when each of `client_ids` was last used. Credentials that were never used are left
+/// out of the map.
+///
+/// # Errors
+/// Returns an error if the database query fails.
+pub async fn last_used_at(
+    db: &Connection,
+    client_ids: Vec<String>,
+) -> Result<HashMap<String, NaiveDateTime>> {
+    let usage = CredentialUsage::find()
+        .filter(credential_usage::Column::ClientId.is_in(client_ids))
+        .all(db.get())
+        .await?;
+
+    Ok(usage
+        .into_iter()
+        .map(|u| (u.client_id, u.last_used_at))
+        .collect())
+}
+
+/// Removes the recorded usage of a deleted credential.
+///
+/// # Errors
+/// Returns an error if the database writes fail.
+pub async fn delete(db: &Connection, client_id: &str) -> Result<()> {
+    CredentialUsage::delete_many()
+        .filter(credential_usage::Column::ClientId.eq(client_id))
+        .exec(db.get())
+        .await?;
+
+    CredentialUsageBuckets::delete_many()
+        .filter(credential_usage_buckets::Column::ClientId.eq(client_id))
+        .exec(db.get())
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,404 @@
+mod support;
+
+use holaplex_hub_credentials::{
+    backend::Fault,
+    entities::{credentials, sea_orm_active_enums::CredentialStatus},
+    proto::credential_events::Event,
+};
+use hub_core::{chrono::NaiveDateTime, uuid::Uuid};
+use ory_openapi_generated_client::models::OAuth2Client;
+use serde_json::{json, Value};
+use support::{client, Fixtures, Harness};
+
+const CREATE_CREDENTIAL: &str = r"
+mutation CreateCredential($input: CreateCredentialInput!) {
+  createCredential(input: $input) {
+    credential { clientId name organizationId createdById scopes status }
+    clientSecret
+    accessToken { accessToken tokenType }
+  }
+}
+";
+
+const EDIT_CREDENTIAL: &str = r"
+mutation EditCredential($input: EditCredentialInput!) {
+  editCredential(input: $input) {
+    credential { clientId name }
+  }
+}
+";
+
+const DELETE_CREDENTIAL: &str = r"
+mutation DeleteCredential($input: DeleteCredentialInput!) {
+  deleteCredential(input: $input) {
+    credential
+  }
+}
+";
+
+const ORGANIZATION_CREDENTIALS: &str = r"
+query OrganizationCredentials($representations: [_Any!]!) {
+  _entities(representations: $representations) {
+    ... on Organization {
+      credentials { clientId name }
+    }
+  }
+}
+";
+
+const CREDENTIAL: &str = r"
+query Credential($representations: [_Any!]!) {
+  _entities(representations: $representations) {
+    ... on Credential { clientId name description organizationId }
+  }
+}
+";
+
+fn credential_representation(client_id: &str) -> Value {
+    json!({ "representations": [{ "__typename": "Credential", "clientId": client_id }] })
+}
+
+fn error_message(body: &Value) -> &str {
+    body["errors"][0]["message"].as_str().unwrap_or_default()
+}
+
+#[tokio::test]
+async fn creates_credential() {
+    let organization = Uuid::new_v4();
+    let user = Uuid::new_v4();
+
+    let app = Harness::start(Fixtures {
+        active_counts: vec![0],
+        ..Fixtures::default()
+    })
+    .await;
+    app.add_member(organization, user);
+
+    let body = app
+        .graphql(
+            user,
+            CREATE_CREDENTIAL,
+            json!({
+                "input": {
+                    "organization": organization.to_string(),
+                    "name": "ci",
+                    "scopes": ["DROPS_READ"],
+                },
+            }),
+        )
+        .await;
+
+    assert_eq!(body["errors"], Value::Null, "{body}");
+
+    let payload = &body["data"]["createCredential"];
+    let client_id = payload["credential"]["clientId"].as_str().unwrap();
+
+    assert_eq!(payload["credential"]["name"], "ci");
+    assert_eq!(
+        payload["credential"]["organizationId"],
+        organization.to_string()
+    );
+    assert_eq!(payload["credential"]["createdById"], user.to_string());
+    assert_eq!(payload["credential"]["scopes"], json!(["DROPS_READ"]));
+    assert_eq!(payload["credential"]["status"], "ACTIVE");
+    assert!(payload["clientSecret"].is_string());
+    assert!(payload["accessToken"]["accessToken"].is_string());
+
+    let stored = app.client(client_id).await.unwrap();
+
+    assert_eq!(stored.owner, Some(organization.to_string()));
+    assert_eq!(stored.contacts, Some(vec![user.to_string()]));
+    assert_eq!(stored.scope.as_deref(), Some("drops:read"));
+
+    assert!(app.ran("SELECT pg_advisory_xact_lock"));
+    assert!(app.ran(r#"INSERT INTO "credentials""#));
+    assert!(app.ran(r#"INSERT INTO "credential_audit_log""#));
+
+    let events = app.published().await;
+
+    assert_eq!(events.len(), 1);
+
+    let (event, key) = &events[0];
+
+    assert_eq!(key.id, client_id);
+    assert_eq!(key.user_id, user.to_string());
+
+    match &event.event {
+        Some(Event::Oauth2ClientCreated(created)) => {
+            assert_eq!(created.client_id, client_id);
+            assert_eq!(created.organization, organization.to_string());
+            assert_eq!(created.scopes, vec!["drops:read".to_string()]);
+            assert!(created.before.is_none());
+            assert!(created.after.is_some());
+        },
+        event => panic!("unexpected event {event:?}"),
+    }
+}
+
+#[tokio::test]
+async fn refuses_to_create_credential_for_non_member() {
+    let app = Harness::start(Fixtures::default()).await;
+
+    let body = app
+        .graphql(
+            Uuid::new_v4(),
+            CREATE_CREDENTIAL,
+            json!({ "input": { "organization": Uuid::new_v4().to_string(), "name": "ci" } }),
+        )
+        .await;
+
+    assert_eq!(body["errors"][0]["extensions"]["code"], "FORBIDDEN");
+    assert_eq!(app.hydra.client_count(), 0);
+    assert!(!app.ran(r#"INSERT INTO "credentials""#));
+    assert!(app.published().await.is_empty());
+}
+
+#[tokio::test]
+async fn edits_credential() {
+    let organization = Uuid::new_v4();
+    let user = Uuid::new_v4();
+
+    let app = Harness::start(Fixtures {
+        metadata: vec![vec![], vec![]],
+        ..Fixtures::default()
+    })
+    .await;
+    app.add_member(organization, user);
+    let client_id = app.insert_client(client(organization, user, "old"));
+
+    let body = app
+        .graphql(
+            user,
+            EDIT_CREDENTIAL,
+            json!({ "input": { "clientId": client_id, "name": "new" } }),
+        )
+        .await;
+
+    assert_eq!(body["errors"], Value::Null, "{body}");
+    assert_eq!(body["data"]["editCredential"]["credential"]["name"], "new");
+    assert_eq!(
+        app.client(&client_id).await.unwrap().client_name.as_deref(),
+        Some("new")
+    );
+
+    assert!(app.ran(r#"INSERT INTO "credentials""#));
+    assert!(app.ran(r#"INSERT INTO "credential_audit_log""#));
+
+    let events = app.published().await;
+
+    assert_eq!(events.len(), 1);
+
+    match &events[0].0.event {
+        Some(Event::Oauth2ClientUpdated(updated)) => {
+            assert_eq!(updated.changes.len(), 1);
+            assert_eq!(updated.changes[0].field, "name");
+            assert_eq!(updated.changes[0].old_value, "old");
+            assert_eq!(updated.changes[0].new_value, "new");
+
+            let client = updated.client.as_ref().unwrap();
+
+            assert_eq!(client.before.as_ref().unwrap().name, "old");
+            assert_eq!(client.after.as_ref().unwrap().name, "new");
+        },
+        event => panic!("unexpected event {event:?}"),
+    }
+}
+
+#[tokio::test]
+async fn deletes_credential() {
+    let organization = Uuid::new_v4();
+    let user = Uuid::new_v4();
+
+    let app = Harness::start(Fixtures {
+        metadata: vec![vec![]],
+        ..Fixtures::default()
+    })
+    .await;
+    app.add_member(organization, user);
+    let client_id = app.insert_client(client(organization, user, "ci"));
+
+    let body = app
+        .graphql(
+            user,
+            DELETE_CREDENTIAL,
+            json!({ "input": { "credential": client_id } }),
+        )
+        .await;
+
+    assert_eq!(body["errors"], Value::Null, "{body}");
+    assert_eq!(body["data"]["deleteCredential"]["credential"], client_id);
+    assert!(app.client(&client_id).await.is_none());
+
+    assert!(app.ran(r#"UPDATE "credentials" SET "deleted_by_id""#));
+    assert!(app.ran(r#"UPDATE "credentials" SET "client_deleted_at""#));
+    assert!(app.ran(r#"INSERT INTO "credential_audit_log""#));
+
+    let events = app.published().await;
+
+    assert!(matches!(
+        events.as_slice(),
+        [
+            (revoked, _),
+            (deleted, _),
+        ] if matches!(revoked.event, Some(Event::Oauth2ClientTokensRevoked(_)))
+            && matches!(deleted.event, Some(Event::Oauth2ClientDeleted(_)))
+    ));
+}
+
+#[tokio::test]
+async fn lists_credentials_of_organization_across_pages() {
+    let organization = Uuid::new_v4();
+    let user = Uuid::new_v4();
+
+    let app = Harness::start(Fixtures {
+        metadata: vec![vec![]],
+        ..Fixtures::default()
+    })
+    .await;
+    app.add_member(organization, user);
+
+    for name in ["a", "b", "c"] {
+        app.insert_client(client(organization, user, name));
+    }
+    app.insert_client(client(Uuid::new_v4(), user, "other"));
+
+    let body = app
+        .graphql(
+            user,
+            ORGANIZATION_CREDENTIALS,
+            json!({
+                "representations": [{
+                    "__typename": "Organization",
+                    "id": organization.to_string(),
+                }],
+            }),
+        )
+        .await;
+
+    assert_eq!(body["errors"], Value::Null, "{body}");
+
+    let mut names = body["data"]["_entities"][0]["credentials"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c["name"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+
+    assert_eq!(names, vec!["a", "b", "c"]);
+}
+
+#[tokio::test]
+async fn resolves_credential_entity_with_stored_metadata() {
+    let organization = Uuid::new_v4();
+    let user = Uuid::new_v4();
+
+    let client_id = Uuid::new_v4().to_string();
+
+    let metadata = credentials::Model {
+        client_id: client_id.clone(),
+        organization_id: organization,
+        name: "ci".to_string(),
+        description: Some("deploys drops".to_string()),
+        scopes: vec!["drops:read".to_string(), "drops:write".to_string()],
+        status: CredentialStatus::Active,
+        created_by_id: user,
+        created_at: NaiveDateTime::from_timestamp_opt(1_682_942_400, 0).unwrap(),
+        updated_at: None,
+        updated_by_id: None,
+        deleted_by_id: None,
+        deleted_at: None,
+        expires_at: None,
+        expiry_warning_days: None,
+        client_deleted_at: None,
+    };
+
+    let app = Harness::start(Fixtures {
+        metadata: vec![vec![metadata]],
+        ..Fixtures::default()
+    })
+    .await;
+    app.add_member(organization, user);
+    app.insert_client(OAuth2Client {
+        client_id: Some(client_id.clone()),
+        ..client(organization, user, "ci")
+    });
+
+    let body = app
+        .graphql(user, CREDENTIAL, credential_representation(&client_id))
+        .await;
+
+    assert_eq!(body["errors"], Value::Null, "{body}");
+
+    let credential = &body["data"]["_entities"][0];
+
+    assert_eq!(credential["clientId"], client_id);
+    assert_eq!(credential["description"], "deploys drops");
+    assert_eq!(credential["organizationId"], organization.to_string());
+}
+
+#[tokio::test]
+async fn rejects_malformed_clients() {
+    let organization = Uuid::new_v4();
+    let user = Uuid::new_v4();
+
+    let cases = [
+        ("client_name", Value::Null, "no client name"),
+        ("contacts", Value::Null, "no contact list"),
+        ("contacts", json!([]), "no contact"),
+        ("owner", Value::Null, "no owner"),
+        ("owner", json!("acme"), "invalid"),
+        ("created_at", Value::Null, "no created_at"),
+        ("created_at", json!("yesterday"), "invalid"),
+        (
+            "client_credentials_grant_access_token_lifespan",
+            json!("90d"),
+            "unknown unit",
+        ),
+    ];
+
+    for (field, value, expected) in cases {
+        let app = Harness::start(Fixtures::default()).await;
+        app.add_member(organization, user);
+
+        let mut malformed = json!(client(organization, user, "ci"));
+        malformed[field] = value.clone();
+        let client_id = app.insert_client(serde_json::from_value(malformed).unwrap());
+
+        let body = app
+            .graphql(user, CREDENTIAL, credential_representation(&client_id))
+            .await;
+
+        assert!(
+            error_message(&body).contains(expected),
+            "{field} = {value}: {body}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn rolls_back_creation_when_token_response_is_malformed() {
+    let organization = Uuid::new_v4();
+    let user = Uuid::new_v4();
+
+    let app = Harness::start(Fixtures {
+        active_counts: vec![0],
+        ..Fixtures::default()
+    })
+    .await;
+    app.add_member(organization, user);
+    app.hydra.inject(Fault::MalformedTokenExchange);
+
+    let body = app
+        .graphql(
+            user,
+            CREATE_CREDENTIAL,
+            json!({ "input": { "organization": organization.to_string(), "name": "ci" } }),
+        )
+        .await;
+
+    assert!(error_message(&body).contains("no access token"), "{body}");
+    assert_eq!(app.hydra.client_count(), 0);
+    assert!(!app.ran(r#"INSERT INTO "credentials""#));
+    assert!(app.published().await.is_empty());
+}
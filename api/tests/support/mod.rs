@@ -0,0 +1,556 @@
+//! A harness running the GraphQL API end to end against the in-memory credential backend served
+//! behind Hydra's admin and public routes, a stand-in for the organizations service, a mock
+//! database and a producer capturing the events relayed from the outbox.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use holaplex_hub_credentials::{
+    backend::{Backend, CredentialBackend, MemoryBackend},
+    cipher::{Cipher, CipherArgs},
+    config::CredentialArgs,
+    db::Connection,
+    graphql::schema::build_schema,
+    handlers::graphql_handler,
+    idempotency::{Idempotency, IdempotencyArgs},
+    introspection::{IntrospectionArgs, IntrospectionCache},
+    membership::{Membership, MembershipArgs},
+    ory_client::{Client, OryArgs},
+    outbox::{OutboxArgs, OutboxMetrics, Relay},
+    producer::EventProducer,
+    proto::{CredentialEventKey, CredentialEvents},
+    AppState,
+};
+use hub_core::{
+    anyhow::Result,
+    clap::{self, Parser},
+    tokio,
+    uuid::Uuid,
+};
+use ory_openapi_generated_client::{
+    apis::Error,
+    models::{JsonPatch, OAuth2Client},
+};
+use poem::{
+    async_trait, delete,
+    endpoint::BoxEndpoint,
+    get, handler,
+    http::{header::LINK, StatusCode},
+    listener::{Acceptor, Listener, TcpListener},
+    middleware::AddData,
+    post,
+    test::TestClient,
+    web::{
+        headers::{authorization::Basic, Authorization},
+        Data, Form, Json, Path, Query, TypedHeader,
+    },
+    EndpointExt, IntoResponse, Response, Route, Server,
+};
+use sea_orm::{
+    DatabaseBackend, MockDatabase, MockExecResult, Statement, Transaction, Value as DbValue,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// The largest number of clients served per page, kept small so listing follows the `Link` header
+/// across pages.
+const PAGE_SIZE_LIMIT: i64 = 2;
+
+/// The number of writes the mock database answers. It fails a statement once its results run out,
+/// so every test is given more than any of them makes and checks the statements it cares about in
+/// the transaction log instead.
+const WRITES: usize = 64;
+
+/// The secret given to the clients tests store directly.
+pub const CLIENT_SECRET: &str = "secret";
+
+/// A well-formed client owned by `organization` and created by `user`.
+pub fn client(organization: Uuid, user: Uuid, name: &str) -> OAuth2Client {
+    OAuth2Client {
+        client_name: Some(name.to_string()),
+        owner: Some(organization.to_string()),
+        contacts: Some(vec![user.to_string()]),
+        scope: Some("drops:read drops:write".to_string()),
+        grant_types: Some(vec!["client_credentials".to_string()]),
+        client_credentials_grant_access_token_lifespan: Some("1h0m0s".to_string()),
+        created_at: Some("2023-05-01T12:00:00Z".to_string()),
+        ..OAuth2Client::new()
+    }
+}
+
+/// Answers with `body` on success, and with the status and body of the backend's error response
+/// otherwise.
+fn respond<T: Serialize, E>(status: StatusCode, result: Result<T, Error<E>>) -> Response {
+    match result {
+        Ok(body) => (status, Json(body)).into_response(),
+        Err(Error::ResponseError(res)) => Response::builder()
+            .status(
+                StatusCode::from_u16(res.status.as_u16())
+                    .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            )
+            .content_type("application/json")
+            .body(res.content),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[handler]
+async fn create_client(
+    Data(hydra): Data<&Arc<MemoryBackend>>,
+    Json(o_auth2_client): Json<OAuth2Client>,
+) -> Response {
+    respond(
+        StatusCode::CREATED,
+        hydra.create_client(&o_auth2_client).await,
+    )
+}
+
+#[handler]
+async fn list_clients(
+    Data(hydra): Data<&Arc<MemoryBackend>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let page_size = query
+        .get("page_size")
+        .and_then(|size| size.parse().ok())
+        .unwrap_or(PAGE_SIZE_LIMIT)
+        .min(PAGE_SIZE_LIMIT);
+
+    let page = match hydra
+        .list_clients_page(
+            query.get("owner").map(String::as_str).unwrap_or_default(),
+            page_size,
+            query.get("page_token").map(String::as_str),
+        )
+        .await
+    {
+        Ok(page) => page,
+        Err(e) => return respond::<(), _>(StatusCode::OK, Err(e)),
+    };
+
+    let mut response = Response::builder().content_type("application/json");
+
+    if let Some(total_count) = page.total_count {
+        response = response.header("x-total-count", total_count.to_string());
+    }
+
+    if let Some(page_token) = page.next_page_token {
+        response = response.header(
+            LINK,
+            format!(
+                r#"<http://hydra/admin/clients?page_size={page_size}&page_token={page_token}>; rel="next""#
+            ),
+        );
+    }
+
+    response.body(json!(page.clients).to_string())
+}
+
+#[handler]
+async fn get_client(Data(hydra): Data<&Arc<MemoryBackend>>, Path(id): Path<String>) -> Response {
+    respond(StatusCode::OK, hydra.get_client(&id).await)
+}
+
+#[handler]
+async fn set_client(
+    Data(hydra): Data<&Arc<MemoryBackend>>,
+    Path(id): Path<String>,
+    Json(o_auth2_client): Json<OAuth2Client>,
+) -> Response {
+    respond(
+        StatusCode::OK,
+        hydra.update_client(&id, &o_auth2_client).await,
+    )
+}
+
+#[handler]
+async fn patch_client(
+    Data(hydra): Data<&Arc<MemoryBackend>>,
+    Path(id): Path<String>,
+    Json(patch): Json<Vec<JsonPatch>>,
+) -> Response {
+    respond(StatusCode::OK, hydra.patch_client(&id, patch).await)
+}
+
+#[handler]
+async fn delete_client(Data(hydra): Data<&Arc<MemoryBackend>>, Path(id): Path<String>) -> Response {
+    match hydra.delete_client(&id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => respond::<(), _>(StatusCode::NO_CONTENT, Err(e)),
+    }
+}
+
+#[handler]
+async fn delete_tokens(
+    Data(hydra): Data<&Arc<MemoryBackend>>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Response {
+    let client_id = query.get("client_id").cloned().unwrap_or_default();
+
+    match hydra.revoke_tokens(&client_id).await {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => respond::<(), _>(StatusCode::NO_CONTENT, Err(e)),
+    }
+}
+
+#[handler]
+async fn exchange_token(
+    Data(hydra): Data<&Arc<MemoryBackend>>,
+    TypedHeader(Authorization(basic)): TypedHeader<Authorization<Basic>>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    respond(
+        StatusCode::OK,
+        hydra
+            .exchange_token(
+                basic.username().to_string(),
+                basic.password().to_string(),
+                form.get("scope").map(String::as_str),
+            )
+            .await,
+    )
+}
+
+#[handler]
+async fn introspect_token(
+    Data(hydra): Data<&Arc<MemoryBackend>>,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    let token = form.get("token").cloned().unwrap_or_default();
+
+    respond(StatusCode::OK, hydra.introspect_token(&token).await)
+}
+
+/// A stand-in for the organizations service, knowing the members of each organization
+#[derive(Debug, Default)]
+pub struct Organizations(Mutex<HashMap<String, Vec<String>>>);
+
+#[handler]
+fn organization_members(
+    Data(organizations): Data<&Arc<Organizations>>,
+    Json(request): Json<Value>,
+) -> Json<Value> {
+    let organization = request["variables"]["id"].as_str().unwrap_or_default();
+
+    let members = organizations
+        .0
+        .lock()
+        .unwrap()
+        .get(organization)
+        .cloned()
+        .unwrap_or_default();
+
+    Json(json!({
+        "data": {
+            "organization": {
+                "members": members
+                    .into_iter()
+                    .map(|user_id| json!({ "userId": user_id }))
+                    .collect::<Vec<_>>(),
+            },
+        },
+    }))
+}
+
+/// Serves `hydra` and `organizations` on a random local port, returning its base URL.
+async fn serve(hydra: Arc<MemoryBackend>, organizations: Arc<Organizations>) -> String {
+    let acceptor = TcpListener::bind("127.0.0.1:0")
+        .into_acceptor()
+        .await
+        .unwrap();
+    let addr = *acceptor.local_addr()[0].as_socket_addr().unwrap();
+
+    let app = Route::new()
+        .at("/admin/clients", post(create_client).get(list_clients))
+        .at(
+            "/admin/clients/:id",
+            get(get_client)
+                .put(set_client)
+                .patch(patch_client)
+                .delete(delete_client),
+        )
+        .at("/admin/oauth2/tokens", delete(delete_tokens))
+        .at("/admin/oauth2/introspect", post(introspect_token))
+        .at("/oauth2/token", post(exchange_token))
+        .at("/organizations/graphql", post(organization_members))
+        .with(AddData::new(hydra))
+        .with(AddData::new(organizations));
+
+    tokio::spawn(Server::new_with_acceptor(acceptor).run(app));
+
+    format!("http://{addr}")
+}
+
+/// The rows the mock database answers the API's reads with, in order
+#[derive(Debug, Default)]
+pub struct Fixtures {
+    /// Active credential counts answering the quota checks, which precede any metadata lookup.
+    pub active_counts: Vec<i64>,
+    /// Results of the credential metadata lookups.
+    pub metadata: Vec<Vec<credentials::Model>>,
+}
+
+fn database(fixtures: Fixtures) -> Connection {
+    let Fixtures {
+        active_counts,
+        metadata,
+    } = fixtures;
+
+    MockDatabase::new(DatabaseBackend::Postgres)
+        .append_query_results(
+            active_counts
+                .into_iter()
+                .map(|count| {
+                    vec![BTreeMap::from([(
+                        "num_items",
+                        DbValue::BigInt(Some(count)),
+                    )])]
+                })
+                .collect::<Vec<_>>(),
+        )
+        .append_query_results(metadata)
+        .append_exec_results((0..WRITES).map(|_| MockExecResult {
+            last_insert_id: 0,
+            rows_affected: 1,
+        }))
+        .into_connection()
+        .into()
+}
+
+#[derive(clap::Parser)]
+struct Config {
+    #[command(flatten)]
+    credentials: CredentialArgs,
+}
+
+/// The service under test and its collaborators
+pub struct Harness {
+    pub hydra: Arc<MemoryBackend>,
+    pub db: Connection,
+    organizations: Arc<Organizations>,
+    client: TestClient<BoxEndpoint<'static>>,
+    log: Mutex<Vec<Statement>>,
+}
+
+impl Harness {
+    /// Starts the stand-ins and builds the API over a mock database answering with `fixtures`.
+    pub async fn start(fixtures: Fixtures) -> Self {
+        let hydra = Arc::new(MemoryBackend::default());
+        let organizations = Arc::new(Organizations::default());
+        let base_url = serve(hydra.clone(), organizations.clone()).await;
+        let db = database(fixtures);
+
+        let ory: Backend = Arc::new(Client::new(OryArgs {
+            ory_admin_base_url: base_url.clone(),
+            ory_public_base_url: base_url.clone(),
+            ory_auth_token: String::new(),
+        }));
+
+        let Config { credentials } = Config::parse_from(["hub-credentials"]);
+
+        let state = AppState::new(
+            build_schema(),
+            db.clone(),
+            ory,
+            credentials,
+            Membership::new(MembershipArgs {
+                organizations_graphql_endpoint: format!("{base_url}/organizations/graphql"),
+            }),
+            Idempotency::new(IdempotencyArgs {
+                idempotency_key_ttl: 60,
+                idempotency_reservation_lease: 60,
+                idempotency_encryption_key: "00".repeat(32),
+            })
+            .unwrap(),
+            Cipher::from_args(&CipherArgs {
+                token_revocation_encryption_key: "00".repeat(32),
+            })
+            .unwrap(),
+            IntrospectionCache::new(IntrospectionArgs {
+                introspection_cache_ttl: 0,
+            }),
+        );
+
+        let app = Route::new()
+            .at("/graphql", post(graphql_handler))
+            .with(AddData::new(state))
+            .boxed();
+
+        Self {
+            hydra,
+            db,
+            organizations,
+            client: TestClient::new(app),
+            log: Mutex::default(),
+        }
+    }
+
+    /// Makes `user` a member of `organization`.
+    pub fn add_member(&self, organization: Uuid, user: Uuid) {
+        self.organizations
+            .0
+            .lock()
+            .unwrap()
+            .entry(organization.to_string())
+            .or_default()
+            .push(user.to_string());
+    }
+
+    /// Stores `o_auth2_client` as it is, malformed or not, with [`CLIENT_SECRET`] as its secret,
+    /// returning its client ID.
+    pub fn insert_client(&self, o_auth2_client: OAuth2Client) -> String {
+        self.hydra.insert_client(o_auth2_client, CLIENT_SECRET)
+    }
+
+    /// The stored client with `client_id`, if it exists.
+    pub async fn client(&self, client_id: &str) -> Option<OAuth2Client> {
+        self.hydra.get_client(client_id).await.ok()
+    }
+
+    /// Runs `query` with `variables` through `graphql_handler` as `user`, returning the response
+    /// body.
+    pub async fn graphql(&self, user: Uuid, query: &str, variables: Value) -> Value {
+        let response = self
+            .client
+            .post("/graphql")
+            .header("X-USER-ID", user.to_string())
+            .body_json(&json!({ "query": query, "variables": variables }))
+            .send()
+            .await;
+
+        response.assert_status_is_ok();
+
+        response.0.into_body().into_json().await.unwrap()
+    }
+
+    /// The statements the API committed to the database so far, in the order they ran. Statements
+    /// of transactions that were rolled back are not included.
+    pub fn statements(&self) -> Vec<Statement> {
+        let committed = self
+            .db
+            .get()
+            .as_mock_connection()
+            .get_mocker_mutex()
+            .lock()
+            .unwrap()
+            .drain_transaction_log();
+
+        let mut log = self.log.lock().unwrap();
+        log.extend(committed.into_iter().flat_map(Transaction::into_statements));
+
+        log.clone()
+    }
+
+    /// Whether the API committed a statement whose SQL starts with `prefix`.
+    pub fn ran(&self, prefix: &str) -> bool {
+        self.statements()
+            .iter()
+            .any(|statement| statement.sql.starts_with(prefix))
+    }
+
+    /// Relays the events the API wrote to the outbox through a capturing producer, returning them
+    /// in the order they were published.
+    pub async fn published(&self) -> Vec<(CredentialEvents, CredentialEventKey)> {
+        let rows = outbox_rows(&self.statements());
+
+        let relay_db: Connection = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results(vec![vec![BTreeMap::from([(
+                "locked",
+                DbValue::Bool(Some(true)),
+            )])]])
+            .append_query_results(vec![rows
+                .iter()
+                .map(|row| {
+                    row.iter()
+                        .map(|(column, value)| (column.as_str(), value.clone()))
+                        .collect::<BTreeMap<_, _>>()
+                })
+                .collect::<Vec<_>>()])
+            .append_exec_results(rows.iter().map(|_| MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 1,
+            }))
+            .into_connection()
+            .into();
+
+        let producer = CapturingProducer::default();
+        let relay = Relay::new(
+            relay_db,
+            producer.clone(),
+            OutboxMetrics::default(),
+            OutboxArgs {
+                outbox_poll_interval_ms: 10,
+                outbox_batch_size: 100,
+                outbox_max_attempts: 20,
+            },
+        );
+
+        let task = tokio::spawn(relay.run());
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while producer.len() < rows.len() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("outbox events were not relayed");
+
+        task.abort();
+
+        producer.take()
+    }
+}
+
+/// Reads back the outbox rows inserted by the API from the statements it committed, pairing the
+/// values of each insert with the columns it names. The mock database assigns no IDs, so rows are
+/// numbered in the order they were inserted.
+fn outbox_rows(statements: &[Statement]) -> Vec<BTreeMap<String, DbValue>> {
+    statements
+        .iter()
+        .filter_map(|statement| {
+            let columns = statement
+                .sql
+                .strip_prefix(r#"INSERT INTO "credential_event_outbox" ("#)?
+                .split_once(')')?
+                .0
+                .split(", ")
+                .map(|column| column.trim_matches('"').to_string())
+                .collect::<Vec<_>>();
+
+            let values = statement.values.clone().map(|v| v.0).unwrap_or_default();
+
+            Some(columns.into_iter().zip(values).collect::<BTreeMap<_, _>>())
+        })
+        .zip(1_i64..)
+        .map(|(mut row, id)| {
+            row.insert("id".to_string(), DbValue::BigInt(Some(id)));
+            row
+        })
+        .collect()
+}
+
+/// A producer keeping every event published to it
+#[derive(Debug, Clone, Default)]
+struct CapturingProducer(Arc<Mutex<Vec<(CredentialEvents, CredentialEventKey)>>>);
+
+impl CapturingProducer {
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    fn take(&self) -> Vec<(CredentialEvents, CredentialEventKey)> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+#[async_trait]
+impl EventProducer for CapturingProducer {
+    async fn publish(&self, event: &CredentialEvents, key: &CredentialEventKey) -> Result<()> {
+        self.0.lock().unwrap().push((event.clone(), key.clone()));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,38 @@
+pub use sea_orm_migration::prelude::*;
+
+mod m20230420_000001_create_credential_usage_table;
+mod m20230420_000002_create_credential_usage_buckets_table;
+mod m20230427_000001_create_credentials_table;
+mod m20230502_000001_add_updated_by_id_to_credentials;
+mod m20230508_000001_create_idempotency_keys_table;
+mod m20230512_000001_create_credential_event_outbox_table;
+mod m20230515_000001_add_expiry_to_credentials;
+mod m20230517_000001_create_credential_audit_log_table;
+mod m20230522_000001_create_token_revocations_table;
+mod m20230524_000001_create_credential_event_dead_letters_table;
+mod m20230525_000001_add_token_issued_to_credential_audit_action;
+mod m20230526_000001_add_client_deleted_at_to_credentials;
+mod m20230527_000001_add_token_revoked_to_credential_audit_action;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20230420_000001_create_credential_usage_table::Migration),
+            Box::new(m20230420_000002_create_credential_usage_buckets_table::Migration),
+            Box::new(m20230427_000001_create_credentials_table::Migration),
+            Box::new(m20230502_000001_add_updated_by_id_to_credentials::Migration),
+            Box::new(m20230508_000001_create_idempotency_keys_table::Migration),
+            Box::new(m20230512_000001_create_credential_event_outbox_table::Migration),
+            Box::new(m20230515_000001_add_expiry_to_credentials::Migration),
+            Box::new(m20230517_000001_create_credential_audit_log_table::Migration),
+            Box::new(m20230522_000001_create_token_revocations_table::Migration),
+            Box::new(m20230524_000001_create_credential_event_dead_letters_table::Migration),
+            Box::new(m20230525_000001_add_token_issued_to_credential_audit_action::Migration),
+            Box::new(m20230526_000001_add_client_deleted_at_to_credentials::Migration),
+            Box::new(m20230527_000001_add_token_revoked_to_credential_audit_action::Migration),
+        ]
+    }
+}
@@ -0,0 +1,44 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CredentialUsage::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CredentialUsage::ClientId)
+                            .text()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialUsage::LastUsedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(CredentialUsage::LastUsedIp).text())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CredentialUsage::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum CredentialUsage {
+    Table,
+    ClientId,
+    LastUsedAt,
+    LastUsedIp,
+}
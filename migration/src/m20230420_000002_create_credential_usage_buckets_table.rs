@@ -0,0 +1,57 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CredentialUsageBuckets::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CredentialUsageBuckets::ClientId)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialUsageBuckets::Day)
+                            .date()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialUsageBuckets::Requests)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .primary_key(
+                        Index::create()
+                            .col(CredentialUsageBuckets::ClientId)
+                            .col(CredentialUsageBuckets::Day),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(CredentialUsageBuckets::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum CredentialUsageBuckets {
+    Table,
+    ClientId,
+    Day,
+    Requests,
+}
@@ -0,0 +1,105 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(CredentialStatus::Type)
+                    .values([CredentialStatus::Active, CredentialStatus::Suspended])
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Credentials::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Credentials::ClientId)
+                            .text()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(Credentials::OrganizationId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Credentials::Name).text().not_null())
+                    .col(ColumnDef::new(Credentials::Description).text())
+                    .col(
+                        ColumnDef::new(Credentials::Scopes)
+                            .array(ColumnType::Text)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(Credentials::Status)
+                            .custom(CredentialStatus::Type)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(Credentials::CreatedById).uuid().not_null())
+                    .col(
+                        ColumnDef::new(Credentials::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra("default now()".to_string()),
+                    )
+                    .col(ColumnDef::new(Credentials::UpdatedAt).timestamp())
+                    .col(ColumnDef::new(Credentials::DeletedById).uuid())
+                    .col(ColumnDef::new(Credentials::DeletedAt).timestamp())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("credentials_organization_id_idx")
+                    .table(Credentials::Table)
+                    .col(Credentials::OrganizationId)
+                    .index_type(IndexType::Hash)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Credentials::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(CredentialStatus::Type).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Credentials {
+    Table,
+    ClientId,
+    OrganizationId,
+    Name,
+    Description,
+    Scopes,
+    Status,
+    CreatedById,
+    CreatedAt,
+    UpdatedAt,
+    DeletedById,
+    DeletedAt,
+}
+
+#[derive(Iden)]
+enum CredentialStatus {
+    #[iden = "credential_status"]
+    Type,
+    Active,
+    Suspended,
+}
@@ -0,0 +1,76 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CredentialEventOutbox::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CredentialEventOutbox::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialEventOutbox::PartitionKey)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialEventOutbox::Key)
+                            .binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialEventOutbox::Event)
+                            .binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialEventOutbox::Attempts)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(CredentialEventOutbox::LastError).text())
+                    .col(
+                        ColumnDef::new(CredentialEventOutbox::NextAttemptAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialEventOutbox::CreatedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CredentialEventOutbox::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum CredentialEventOutbox {
+    Table,
+    Id,
+    PartitionKey,
+    Key,
+    Event,
+    Attempts,
+    LastError,
+    NextAttemptAt,
+    CreatedAt,
+}
@@ -0,0 +1,57 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Credentials::Table)
+                    .add_column(ColumnDef::new(Credentials::ExpiresAt).timestamp())
+                    .add_column(ColumnDef::new(Credentials::ExpiryWarningDays).integer())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("credentials_expires_at_idx")
+                    .table(Credentials::Table)
+                    .col(Credentials::ExpiresAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("credentials_expires_at_idx")
+                    .table(Credentials::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Credentials::Table)
+                    .drop_column(Credentials::ExpiresAt)
+                    .drop_column(Credentials::ExpiryWarningDays)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum Credentials {
+    Table,
+    ExpiresAt,
+    ExpiryWarningDays,
+}
@@ -0,0 +1,130 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_type(
+                Type::create()
+                    .as_enum(CredentialAuditAction::Type)
+                    .values([
+                        CredentialAuditAction::Created,
+                        CredentialAuditAction::Updated,
+                        CredentialAuditAction::SecretRotated,
+                        CredentialAuditAction::TokensRevoked,
+                        CredentialAuditAction::Suspended,
+                        CredentialAuditAction::Reactivated,
+                        CredentialAuditAction::Deleted,
+                    ])
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(CredentialAuditLog::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CredentialAuditLog::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialAuditLog::OrganizationId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialAuditLog::ClientId)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialAuditLog::Action)
+                            .custom(CredentialAuditAction::Type)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(CredentialAuditLog::ActorId).uuid())
+                    .col(ColumnDef::new(CredentialAuditLog::Ip).text())
+                    .col(ColumnDef::new(CredentialAuditLog::UserAgent).text())
+                    .col(
+                        ColumnDef::new(CredentialAuditLog::Diff)
+                            .json_binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialAuditLog::CreatedAt)
+                            .timestamp()
+                            .not_null()
+                            .extra("default now()".to_string()),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("credential_audit_log_organization_id_id_idx")
+                    .table(CredentialAuditLog::Table)
+                    .col(CredentialAuditLog::OrganizationId)
+                    .col(CredentialAuditLog::Id)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("credential_audit_log_client_id_id_idx")
+                    .table(CredentialAuditLog::Table)
+                    .col(CredentialAuditLog::ClientId)
+                    .col(CredentialAuditLog::Id)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(CredentialAuditLog::Table).to_owned())
+            .await?;
+
+        manager
+            .drop_type(Type::drop().name(CredentialAuditAction::Type).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum CredentialAuditLog {
+    Table,
+    Id,
+    OrganizationId,
+    ClientId,
+    Action,
+    ActorId,
+    Ip,
+    UserAgent,
+    Diff,
+    CreatedAt,
+}
+
+#[derive(Iden)]
+enum CredentialAuditAction {
+    #[iden = "credential_audit_action"]
+    Type,
+    Created,
+    Updated,
+    SecretRotated,
+    TokensRevoked,
+    Suspended,
+    Reactivated,
+    Deleted,
+}
@@ -0,0 +1,76 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(TokenRevocations::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(TokenRevocations::Id)
+                            .big_integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(TokenRevocations::ClientId).text().not_null())
+                    .col(
+                        ColumnDef::new(TokenRevocations::Credentials)
+                            .binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TokenRevocations::RevokeAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(TokenRevocations::Attempts)
+                            .integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .col(ColumnDef::new(TokenRevocations::LastError).text())
+                    .col(
+                        ColumnDef::new(TokenRevocations::CreatedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("token_revocations_revoke_at_idx")
+                    .table(TokenRevocations::Table)
+                    .col(TokenRevocations::RevokeAt)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(TokenRevocations::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum TokenRevocations {
+    Table,
+    Id,
+    ClientId,
+    Credentials,
+    RevokeAt,
+    Attempts,
+    LastError,
+    CreatedAt,
+}
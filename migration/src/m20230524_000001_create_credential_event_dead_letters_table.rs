@@ -0,0 +1,105 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(CredentialEventDeadLetters::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(CredentialEventDeadLetters::Id)
+                            .big_integer()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialEventDeadLetters::PartitionKey)
+                            .text()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialEventDeadLetters::Key)
+                            .binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialEventDeadLetters::Event)
+                            .binary()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialEventDeadLetters::Attempts)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(CredentialEventDeadLetters::LastError).text())
+                    .col(
+                        ColumnDef::new(CredentialEventDeadLetters::CreatedAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(CredentialEventDeadLetters::DeadLetteredAt)
+                            .timestamp()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                IndexCreateStatement::new()
+                    .name("credential_event_outbox_partition_key_idx")
+                    .table(CredentialEventOutbox::Table)
+                    .col(CredentialEventOutbox::PartitionKey)
+                    .col(CredentialEventOutbox::Id)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("credential_event_outbox_partition_key_idx")
+                    .table(CredentialEventOutbox::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(CredentialEventDeadLetters::Table)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(Iden)]
+enum CredentialEventDeadLetters {
+    Table,
+    Id,
+    PartitionKey,
+    Key,
+    Event,
+    Attempts,
+    LastError,
+    CreatedAt,
+    DeadLetteredAt,
+}
+
+#[derive(Iden)]
+enum CredentialEventOutbox {
+    Table,
+    Id,
+    PartitionKey,
+}
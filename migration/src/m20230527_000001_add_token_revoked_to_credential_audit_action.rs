@@ -0,0 +1,30 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_type(
+                Type::alter()
+                    .name(CredentialAuditAction::Type)
+                    .add_value(CredentialAuditAction::TokenRevoked)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    /// Postgres cannot drop a value from an enum, so `token_revoked` is left in place.
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
+
+#[derive(Iden)]
+enum CredentialAuditAction {
+    #[iden = "credential_audit_action"]
+    Type,
+    TokenRevoked,
+}